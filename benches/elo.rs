@@ -0,0 +1,161 @@
+use elo::elo::{
+    apply_records, get_player_rating_history, get_rating, Character, EloRatingMode,
+    EloRatingSetting, EloRatingState, EloRecord, EloSetting, Player, Tournament, TournamentSub,
+    TournamentTier,
+};
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+// 大きな合成データで EloSetting の検索とレーティング計算の速さを測る
+// cargo bench で実行する（計測は std::time::Instant、各項目を RUN_COUNT 回実行した中央値）
+
+const PLAYER_COUNT: u32 = 20_000;
+const CHARACTER_COUNT: u32 = 50;
+const TOURNAMENT_COUNT: u32 = 500;
+const TOURNAMENT_SUB_COUNT: u32 = 10;
+const RECORD_COUNT: usize = 200_000;
+const LOOKUP_COUNT: usize = 1_000_000;
+const RUN_COUNT: usize = 5;
+
+// 再現できるように固定の種の線形合同法で作る
+struct Lcg(u64);
+
+impl Lcg {
+    fn next(&mut self, n: u32) -> u32 {
+        self.0 = self
+            .0
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        ((self.0 >> 33) % n as u64) as u32
+    }
+}
+
+fn create_setting() -> EloSetting {
+    let tiers = [
+        TournamentTier::Weekly,
+        TournamentTier::Regional,
+        TournamentTier::Major,
+    ];
+    EloSetting::new(
+        (1..=PLAYER_COUNT)
+            .map(|id| Player::new(id, &format!("player{}", id)))
+            .collect(),
+        (1..=TOURNAMENT_COUNT)
+            .map(|id| Tournament {
+                id,
+                name: format!("tournament{}", id),
+                tier: tiers[id as usize % tiers.len()],
+            })
+            .collect(),
+        (1..=TOURNAMENT_SUB_COUNT)
+            .map(|id| TournamentSub {
+                id,
+                name: format!("sub{}", id),
+            })
+            .collect(),
+        (1..=CHARACTER_COUNT)
+            .map(|id| Character {
+                id,
+                name: format!("character{}", id),
+            })
+            .collect(),
+    )
+}
+
+fn create_records(rng: &mut Lcg) -> Vec<EloRecord> {
+    (0..RECORD_COUNT)
+        .map(|index| {
+            let player_id = rng.next(PLAYER_COUNT) + 1;
+            let opponent_player_id = (player_id + rng.next(PLAYER_COUNT - 1)) % PLAYER_COUNT + 1;
+            let (win_count, lose_count) = if rng.next(2) == 0 {
+                (2, rng.next(2))
+            } else {
+                (rng.next(2), 2)
+            };
+            EloRecord::new(
+                rng.next(TOURNAMENT_COUNT) + 1,
+                rng.next(TOURNAMENT_SUB_COUNT) + 1,
+                index as u64,
+                player_id,
+                rng.next(CHARACTER_COUNT) + 1,
+                opponent_player_id,
+                rng.next(CHARACTER_COUNT) + 1,
+                win_count,
+                lose_count,
+            )
+        })
+        .collect()
+}
+
+fn measure<T>(name: &str, item_count: usize, mut f: impl FnMut() -> T) {
+    let mut times: Vec<Duration> = (0..RUN_COUNT)
+        .map(|_| {
+            let start = Instant::now();
+            black_box(f());
+            start.elapsed()
+        })
+        .collect();
+    times.sort();
+    let median = times[RUN_COUNT / 2];
+    println!(
+        "{}\t{:.1} ms\t{:.0} 件/秒",
+        name,
+        median.as_secs_f64() * 1000_f64,
+        item_count as f64 / median.as_secs_f64()
+    );
+}
+
+fn main() {
+    let mut rng = Lcg(20240101);
+    let record_list = create_records(&mut rng);
+    println!(
+        "選手 {} 人, キャラクター {}, 大会 {}, レコード {} 件",
+        PLAYER_COUNT, CHARACTER_COUNT, TOURNAMENT_COUNT, RECORD_COUNT
+    );
+    println!("項目\t中央値\t処理数");
+
+    measure("EloSetting::new", PLAYER_COUNT as usize, create_setting);
+    let elo_setting = create_setting();
+
+    let lookup_ids: Vec<u32> = (0..LOOKUP_COUNT)
+        .map(|_| rng.next(PLAYER_COUNT) + 1)
+        .collect();
+    measure("find_player", LOOKUP_COUNT, || {
+        lookup_ids
+            .iter()
+            .filter(|id| elo_setting.find_player(**id).is_some())
+            .count()
+    });
+
+    for rating_mode in [
+        EloRatingMode::Player,
+        EloRatingMode::PlayerCharacter,
+        EloRatingMode::Character,
+        EloRatingMode::PlayerAndCharacter,
+    ] {
+        let rating_setting = EloRatingSetting {
+            rating_mode,
+            ..Default::default()
+        };
+        measure(
+            &format!("get_rating ({:?})", rating_mode),
+            RECORD_COUNT,
+            || get_rating(&elo_setting, &record_list, &rating_setting).unwrap(),
+        );
+    }
+
+    let rating_setting = EloRatingSetting::default();
+    measure("get_player_rating_history", RECORD_COUNT, || {
+        get_player_rating_history(&elo_setting, &record_list, &rating_setting).unwrap()
+    });
+
+    // 9割を反映した状態に残りの1割を足す
+    let (old_record_list, new_record_list) = record_list.split_at(RECORD_COUNT / 10 * 9);
+    let mut state = EloRatingState::new(&elo_setting, &rating_setting);
+    apply_records(&mut state, &elo_setting, old_record_list, &rating_setting).unwrap();
+    measure("apply_records (1割)", new_record_list.len(), || {
+        let mut state = state.clone();
+        apply_records(&mut state, &elo_setting, new_record_list, &rating_setting).unwrap();
+        state
+    });
+}
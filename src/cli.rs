@@ -0,0 +1,94 @@
+use elo::sfl::{SflRatingSetting, SflStage};
+use elo::simulate::SimulateSetting;
+use std::str::FromStr;
+
+pub const USAGE: &str = "\
+使い方: elo <COMMAND> [OPTIONS]
+
+COMMAND:
+    simulate    残り試合をシミュレーションして順位ごとの回数を表示する
+    ratings     すでに行われた結果からレーティングを表示する
+    standings   現在のポイントとバトル得失を表示する
+    season      リーグ戦からグランドファイナルまでを通してシミュレーションし、各ラウンドへの進出確率を表示する
+    html        シミュレーション結果から勝ち抜け予想ページを生成する
+    serve       試合結果を入力して再計算できる勝ち抜け予想ページをローカルで配信する
+
+OPTIONS:
+    --season <FILE>     シーズン定義ファイル [default: data/sfl2024.json]
+    --stage <STAGE>     対象のステージ（JP2024DivisionS など）。省略時は全ディビジョン。season では使わない
+    --trials <N>        試行回数 [default: 10000]
+    --seed <N>          乱数シード [default: 5]
+    --threads <N>       試行を並列に実行するスレッド数 [default: CPU数]
+    --rating <SETTING>  NoRating | TeamOnly | HomeAway | GameType | HomeAwayGameType | Player [default: HomeAwayGameType]
+    --output <FILE>     html の出力先。省略時は標準出力
+    --address <ADDR>    serve の待ち受けアドレス [default: 127.0.0.1:8080]
+    -h, --help          この説明を表示する";
+
+pub enum Command {
+    Simulate,
+    Ratings,
+    Standings,
+    Season,
+    Html,
+    Serve,
+    Help,
+}
+
+pub struct CliArgs {
+    pub command: Command,
+    pub season_path: String,
+    pub sfl_stage: Option<SflStage>,
+    pub setting: SimulateSetting,
+    pub output_path: Option<String>,
+    pub address: String,
+}
+
+pub fn parse_args(args: &[String]) -> Result<CliArgs, String> {
+    let mut iter = args.iter();
+    let command = match iter.next().map(|s| s.as_str()) {
+        Some("simulate") => Command::Simulate,
+        Some("ratings") => Command::Ratings,
+        Some("standings") => Command::Standings,
+        Some("season") => Command::Season,
+        Some("html") => Command::Html,
+        Some("serve") => Command::Serve,
+        Some("-h") | Some("--help") => Command::Help,
+        Some(other) => return Err(format!("不明なコマンド: {}", other)),
+        None => return Err("コマンドを指定してください".to_string()),
+    };
+    let mut cli_args = CliArgs {
+        command,
+        season_path: "data/sfl2024.json".to_string(),
+        sfl_stage: None,
+        setting: SimulateSetting::default(),
+        output_path: None,
+        address: "127.0.0.1:8080".to_string(),
+    };
+    while let Some(option) = iter.next() {
+        if option == "-h" || option == "--help" {
+            cli_args.command = Command::Help;
+            continue;
+        }
+        let value = iter
+            .next()
+            .ok_or(format!("{} に値を指定してください", option))?;
+        match option.as_str() {
+            "--season" => cli_args.season_path = value.to_owned(),
+            "--output" => cli_args.output_path = Some(value.to_owned()),
+            "--address" => cli_args.address = value.to_owned(),
+            "--stage" => cli_args.sfl_stage = Some(SflStage::from_str(value)?),
+            "--trials" => cli_args.setting.trial_count = parse_number(option, value)?,
+            "--seed" => cli_args.setting.seed = parse_number(option, value)?,
+            "--threads" => cli_args.setting.thread_count = parse_number(option, value)?,
+            "--rating" => cli_args.setting.sfl_rate_setting = SflRatingSetting::from_str(value)?,
+            _ => return Err(format!("不明なオプション: {}", option)),
+        }
+    }
+    Ok(cli_args)
+}
+
+fn parse_number<T: FromStr>(option: &str, value: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("{} の値が数値ではない: {}", option, value))
+}
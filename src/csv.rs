@@ -0,0 +1,743 @@
+use crate::elo::{
+    Character, EloRatingChange, EloRatingKey, EloRecord, EloSetting, Player, Tournament,
+    TournamentSub, TournamentTier,
+};
+use crate::stats::{get_character_name, get_player_name, get_tournament_name};
+use std::collections::HashMap;
+use std::fs;
+use std::str::FromStr;
+
+// 表計算ソフトで管理しているデータの CSV の読み書き
+// 1行目は見出しで、列の名前と順番は下の *_HEADER と同じにする
+// 引用符 (") で囲んだ値にはカンマと "" と改行を書ける
+// エラーは「{行番号}行目: ...」の形で、すべての行の分をまとめて返す
+
+// aliases は別名を | で区切って並べる。なければ空。別名の中の | と \ は前に \ を付ける
+const PLAYER_HEADER: &[&str] = &["id", "name", "aliases"];
+const ALIAS_SEPARATOR: char = '|';
+const ALIAS_ESCAPE: char = '\\';
+const TOURNAMENT_HEADER: &[&str] = &["id", "name", "tier"];
+const TOURNAMENT_SUB_HEADER: &[&str] = &["id", "name"];
+const CHARACTER_HEADER: &[&str] = &["id", "name"];
+// game_results は player 側から見た1本ずつの勝敗を W と L で並べる（例: WLW）。わからなければ空
+const RECORD_HEADER: &[&str] = &[
+    "date",
+    "tournament_id",
+    "tournament_sub_id",
+    "player_id",
+    "character_id",
+    "opponent_player_id",
+    "opponent_character_id",
+    "win_count",
+    "lose_count",
+    "game_results",
+];
+// kind は EloRatingKey の種類。その種類にない id は空
+const RATING_HEADER: &[&str] = &[
+    "kind",
+    "player_id",
+    "player_name",
+    "character_id",
+    "character_name",
+    "rating",
+];
+// kind と character_id は変化したレートの EloRatingKey。player_id はそのレートで戦ったプレイヤー
+const RATING_HISTORY_HEADER: &[&str] = &[
+    "date",
+    "tournament_id",
+    "tournament_name",
+    "tournament_sub_id",
+    "kind",
+    "player_id",
+    "player_name",
+    "character_id",
+    "character_name",
+    "opponent_player_id",
+    "opponent_player_name",
+    "rating_before",
+    "rating_after",
+    "delta",
+];
+
+// 1行を値に分ける
+fn split_line(line: &str) -> Result<Vec<String>, String> {
+    match split_fields(line) {
+        (fields, false) => Ok(fields),
+        (_, true) => Err("引用符が閉じていません".to_string()),
+    }
+}
+
+// (値, 最後の値の引用符が閉じていないか)
+fn split_fields(line: &str) -> (Vec<String>, bool) {
+    let mut fields: Vec<String> = vec![];
+    let mut field = String::new();
+    let mut chars = line.chars().peekable();
+    let mut quoted = false;
+    while let Some(c) = chars.next() {
+        match (quoted, c) {
+            (true, '"') => {
+                if chars.peek() == Some(&'"') {
+                    field.push('"');
+                    chars.next();
+                } else {
+                    quoted = false;
+                }
+            }
+            (false, '"') if field.is_empty() => quoted = true,
+            (false, ',') => fields.push(std::mem::take(&mut field)),
+            (_, c) => field.push(c),
+        }
+    }
+    fields.push(field);
+    (fields, quoted)
+}
+
+fn escape_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+fn format_line(fields: &[String]) -> String {
+    fields
+        .iter()
+        .map(|field| escape_field(field))
+        .collect::<Vec<String>>()
+        .join(",")
+}
+
+fn format_csv(header: &[&str], rows: Vec<Vec<String>>) -> String {
+    let mut lines: Vec<String> = vec![header.join(",")];
+    for row in rows.iter() {
+        lines.push(format_line(row));
+    }
+    lines.join("\n") + "\n"
+}
+
+// 行番号と、その行の値（読めなければエラー）
+type CsvRow = (usize, Result<Vec<String>, String>);
+
+// 見出しを確かめて、行ごとに値に分ける。空行は読み飛ばす
+// 引用符の中で行が終わっていれば次の行につなげて1行として読み、始まりの行番号を付ける
+fn read_rows(text: &str, header: &[&str]) -> Result<Vec<CsvRow>, String> {
+    let mut lines = text
+        .trim_start_matches('\u{feff}')
+        .lines()
+        .enumerate()
+        .map(|(index, line)| (index + 1, line.trim_end_matches('\r')));
+    let header_fields = match lines.next() {
+        Some((_, line)) => split_line(line).map_err(|e| format!("1行目: {}", e))?,
+        None => return Err("見出しの行がありません".to_string()),
+    };
+    if header_fields
+        .iter()
+        .map(|f| f.trim())
+        .ne(header.iter().copied())
+    {
+        return Err(format!(
+            "1行目: 見出しは {} にしてください",
+            header.join(",")
+        ));
+    }
+    let check_fields = |text: &str| {
+        split_line(text).and_then(|fields| {
+            if fields.len() == header.len() {
+                Ok(fields)
+            } else {
+                Err(format!(
+                    "列の数が {} です（{} 列にしてください）",
+                    fields.len(),
+                    header.len()
+                ))
+            }
+        })
+    };
+    let mut rows: Vec<CsvRow> = vec![];
+    // 引用符が閉じていない行 (始まりの行番号, ここまでの内容)
+    let mut pending: Option<(usize, String)> = None;
+    for (line_number, line) in lines {
+        let (start_line_number, text) = match pending.take() {
+            Some((start_line_number, mut text)) => {
+                text.push('\n');
+                text.push_str(line);
+                (start_line_number, text)
+            }
+            None if line.trim().is_empty() => continue,
+            None => (line_number, line.to_string()),
+        };
+        if split_fields(&text).1 {
+            pending = Some((start_line_number, text));
+        } else {
+            rows.push((start_line_number, check_fields(&text)));
+        }
+    }
+    if let Some((start_line_number, text)) = pending {
+        rows.push((start_line_number, check_fields(&text)));
+    }
+    Ok(rows)
+}
+
+// \ の後ろが | と \ 以外なら \ もそのまま残す
+fn split_aliases(value: &str) -> Vec<String> {
+    let mut aliases: Vec<String> = vec![];
+    let mut alias = String::new();
+    let mut chars = value.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            ALIAS_ESCAPE => match chars.peek() {
+                Some(&next) if next == ALIAS_SEPARATOR || next == ALIAS_ESCAPE => {
+                    alias.push(next);
+                    chars.next();
+                }
+                _ => alias.push(c),
+            },
+            ALIAS_SEPARATOR => aliases.push(std::mem::take(&mut alias)),
+            c => alias.push(c),
+        }
+    }
+    aliases.push(alias);
+    aliases
+}
+
+fn join_aliases(aliases: &[String]) -> String {
+    aliases
+        .iter()
+        .map(|alias| {
+            alias
+                .replace(ALIAS_ESCAPE, "\\\\")
+                .replace(ALIAS_SEPARATOR, "\\|")
+        })
+        .collect::<Vec<String>>()
+        .join(&ALIAS_SEPARATOR.to_string())
+}
+
+fn parse_field<T: FromStr>(fields: &[String], header: &[&str], column: usize) -> Result<T, String> {
+    let value = fields[column].trim();
+    value
+        .parse::<T>()
+        .map_err(|_| format!("{} の値が正しくありません: {}", header[column], value))
+}
+
+fn parse_game_results(value: &str) -> Result<Vec<bool>, String> {
+    value
+        .trim()
+        .chars()
+        .map(|c| match c {
+            'W' | 'w' => Ok(true),
+            'L' | 'l' => Ok(false),
+            _ => Err(format!(
+                "game_results は W と L で書いてください: {}",
+                value
+            )),
+        })
+        .collect()
+}
+
+fn format_game_results(game_results: &[bool]) -> String {
+    game_results
+        .iter()
+        .map(|win| if *win { 'W' } else { 'L' })
+        .collect()
+}
+
+// 各行を parse_row で読み、id が重複していないか確かめる
+fn parse_rows<T>(
+    text: &str,
+    header: &[&str],
+    parse_row: impl Fn(&[String]) -> Result<T, String>,
+    get_id: impl Fn(&T) -> Option<u32>,
+) -> Result<Vec<T>, String> {
+    let mut list: Vec<T> = vec![];
+    let mut id_lines: HashMap<u32, usize> = HashMap::new();
+    let mut errors: Vec<String> = vec![];
+    for (line_number, fields) in read_rows(text, header)? {
+        match fields.and_then(|fields| parse_row(&fields)) {
+            Ok(item) => {
+                if let Some(id) = get_id(&item) {
+                    if let Some(first_line_number) = id_lines.insert(id, line_number) {
+                        errors.push(format!(
+                            "{}行目: id {} が {}行目と重複しています",
+                            line_number, id, first_line_number
+                        ));
+                        continue;
+                    }
+                }
+                list.push(item);
+            }
+            Err(e) => errors.push(format!("{}行目: {}", line_number, e)),
+        }
+    }
+    if errors.is_empty() {
+        Ok(list)
+    } else {
+        Err(errors.join("\n"))
+    }
+}
+
+pub fn parse_players_csv(text: &str) -> Result<Vec<Player>, String> {
+    parse_rows(
+        text,
+        PLAYER_HEADER,
+        |fields| {
+            let mut player = Player::new(parse_field(fields, PLAYER_HEADER, 0)?, fields[1].trim());
+            for alias in split_aliases(&fields[2]) {
+                if !alias.trim().is_empty() {
+                    player.add_alias(&alias);
+                }
+            }
+            Ok(player)
+        },
+        |p| Some(p.id),
+    )
+}
+
+pub fn parse_tournaments_csv(text: &str) -> Result<Vec<Tournament>, String> {
+    parse_rows(
+        text,
+        TOURNAMENT_HEADER,
+        |fields| {
+            Ok(Tournament {
+                id: parse_field(fields, TOURNAMENT_HEADER, 0)?,
+                name: fields[1].trim().to_string(),
+                tier: TournamentTier::from_str(fields[2].trim())?,
+            })
+        },
+        |t| Some(t.id),
+    )
+}
+
+pub fn parse_tournament_subs_csv(text: &str) -> Result<Vec<TournamentSub>, String> {
+    parse_rows(
+        text,
+        TOURNAMENT_SUB_HEADER,
+        |fields| {
+            Ok(TournamentSub {
+                id: parse_field(fields, TOURNAMENT_SUB_HEADER, 0)?,
+                name: fields[1].trim().to_string(),
+            })
+        },
+        |t| Some(t.id),
+    )
+}
+
+pub fn parse_characters_csv(text: &str) -> Result<Vec<Character>, String> {
+    parse_rows(
+        text,
+        CHARACTER_HEADER,
+        |fields| {
+            Ok(Character {
+                id: parse_field(fields, CHARACTER_HEADER, 0)?,
+                name: fields[1].trim().to_string(),
+            })
+        },
+        |c| Some(c.id),
+    )
+}
+
+// 値の形だけを確かめる。存在しない id などは elo::validate_record で見る
+pub fn parse_records_csv(text: &str) -> Result<Vec<EloRecord>, String> {
+    parse_rows(
+        text,
+        RECORD_HEADER,
+        |fields| {
+            let record = EloRecord::new(
+                parse_field(fields, RECORD_HEADER, 1)?,
+                parse_field(fields, RECORD_HEADER, 2)?,
+                parse_field(fields, RECORD_HEADER, 0)?,
+                parse_field(fields, RECORD_HEADER, 3)?,
+                parse_field(fields, RECORD_HEADER, 4)?,
+                parse_field(fields, RECORD_HEADER, 5)?,
+                parse_field(fields, RECORD_HEADER, 6)?,
+                parse_field(fields, RECORD_HEADER, 7)?,
+                parse_field(fields, RECORD_HEADER, 8)?,
+            );
+            Ok(record.with_game_results(parse_game_results(&fields[9])?))
+        },
+        |_| None,
+    )
+}
+
+pub fn format_players_csv(player_list: &[Player]) -> String {
+    format_csv(
+        PLAYER_HEADER,
+        player_list
+            .iter()
+            .map(|p| {
+                vec![
+                    p.id.to_string(),
+                    p.name.to_owned(),
+                    join_aliases(&p.aliases),
+                ]
+            })
+            .collect(),
+    )
+}
+
+pub fn format_tournaments_csv(tournament_list: &[Tournament]) -> String {
+    format_csv(
+        TOURNAMENT_HEADER,
+        tournament_list
+            .iter()
+            .map(|t| vec![t.id.to_string(), t.name.to_owned(), format!("{:?}", t.tier)])
+            .collect(),
+    )
+}
+
+pub fn format_tournament_subs_csv(tournament_sub_list: &[TournamentSub]) -> String {
+    format_csv(
+        TOURNAMENT_SUB_HEADER,
+        tournament_sub_list
+            .iter()
+            .map(|t| vec![t.id.to_string(), t.name.to_owned()])
+            .collect(),
+    )
+}
+
+pub fn format_characters_csv(character_list: &[Character]) -> String {
+    format_csv(
+        CHARACTER_HEADER,
+        character_list
+            .iter()
+            .map(|c| vec![c.id.to_string(), c.name.to_owned()])
+            .collect(),
+    )
+}
+
+pub fn format_records_csv(record_list: &[EloRecord]) -> String {
+    format_csv(
+        RECORD_HEADER,
+        record_list
+            .iter()
+            .map(|r| {
+                vec![
+                    r.date.to_string(),
+                    r.tournament_id.to_string(),
+                    r.tournament_sub_id.to_string(),
+                    r.player_id.to_string(),
+                    r.character_id.to_string(),
+                    r.opponent_player_id.to_string(),
+                    r.opponent_character_id.to_string(),
+                    r.win_count.to_string(),
+                    r.lose_count.to_string(),
+                    format_game_results(&r.game_results),
+                ]
+            })
+            .collect(),
+    )
+}
+
+// elo::get_rating の結果。キーの順に並べる
+pub fn format_ratings_csv(
+    elo_setting: &EloSetting,
+    rating_map: &HashMap<EloRatingKey, f64>,
+) -> String {
+    let mut ratings: Vec<(&EloRatingKey, &f64)> = rating_map.iter().collect();
+    ratings.sort_by_key(|(key, _)| **key);
+    let player_fields = |player_id: u32| {
+        [
+            player_id.to_string(),
+            get_player_name(elo_setting, player_id),
+        ]
+    };
+    let character_fields = |character_id: u32| {
+        [
+            character_id.to_string(),
+            get_character_name(elo_setting, character_id),
+        ]
+    };
+    let empty_fields = || [String::new(), String::new()];
+    format_csv(
+        RATING_HEADER,
+        ratings
+            .into_iter()
+            .map(|(key, rating)| {
+                let (kind, player, character) = match *key {
+                    EloRatingKey::Player(player_id) => {
+                        ("Player", player_fields(player_id), empty_fields())
+                    }
+                    EloRatingKey::PlayerCharacter(player_id, character_id) => (
+                        "PlayerCharacter",
+                        player_fields(player_id),
+                        character_fields(character_id),
+                    ),
+                    EloRatingKey::Character(character_id) => {
+                        ("Character", empty_fields(), character_fields(character_id))
+                    }
+                };
+                let mut fields = vec![kind.to_string()];
+                fields.extend(player);
+                fields.extend(character);
+                fields.push(format!("{:.1}", rating));
+                fields
+            })
+            .collect(),
+    )
+}
+
+// elo::get_player_rating_history の結果
+pub fn format_rating_history_csv(elo_setting: &EloSetting, history: &[EloRatingChange]) -> String {
+    format_csv(
+        RATING_HISTORY_HEADER,
+        history
+            .iter()
+            .map(|change| {
+                let (kind, character_id) = match change.key {
+                    EloRatingKey::Player(_) => ("Player", None),
+                    EloRatingKey::PlayerCharacter(_, character_id) => {
+                        ("PlayerCharacter", Some(character_id))
+                    }
+                    EloRatingKey::Character(character_id) => ("Character", Some(character_id)),
+                };
+                vec![
+                    change.date.to_string(),
+                    change.tournament_id.to_string(),
+                    get_tournament_name(elo_setting, change.tournament_id),
+                    change.tournament_sub_id.to_string(),
+                    kind.to_string(),
+                    change.player_id.to_string(),
+                    get_player_name(elo_setting, change.player_id),
+                    character_id.map_or(String::new(), |id| id.to_string()),
+                    character_id.map_or(String::new(), |id| get_character_name(elo_setting, id)),
+                    change.opponent_player_id.to_string(),
+                    get_player_name(elo_setting, change.opponent_player_id),
+                    format!("{:.1}", change.rating_before),
+                    format!("{:.1}", change.rating_after),
+                    format!("{:.1}", change.get_delta()),
+                ]
+            })
+            .collect(),
+    )
+}
+
+// 以下はファイルの読み書き。エラーにはファイル名を付ける
+
+fn read_file<T>(path: &str, parse: impl Fn(&str) -> Result<T, String>) -> Result<T, String> {
+    let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+    parse(&text).map_err(|e| {
+        e.lines()
+            .map(|line| format!("{}: {}", path, line))
+            .collect::<Vec<String>>()
+            .join("\n")
+    })
+}
+
+pub fn write_file(path: &str, text: &str) -> Result<(), String> {
+    fs::write(path, text).map_err(|e| format!("{}: {}", path, e))
+}
+
+pub fn load_elo_setting_csv(
+    player_path: &str,
+    tournament_path: &str,
+    tournament_sub_path: &str,
+    character_path: &str,
+) -> Result<EloSetting, String> {
+    Ok(EloSetting::new(
+        read_file(player_path, parse_players_csv)?,
+        read_file(tournament_path, parse_tournaments_csv)?,
+        read_file(tournament_sub_path, parse_tournament_subs_csv)?,
+        read_file(character_path, parse_characters_csv)?,
+    ))
+}
+
+pub fn save_elo_setting_csv(
+    elo_setting: &EloSetting,
+    player_path: &str,
+    tournament_path: &str,
+    tournament_sub_path: &str,
+    character_path: &str,
+) -> Result<(), String> {
+    write_file(
+        player_path,
+        &format_players_csv(elo_setting.get_player_list()),
+    )?;
+    write_file(
+        tournament_path,
+        &format_tournaments_csv(elo_setting.get_tournament_list()),
+    )?;
+    write_file(
+        tournament_sub_path,
+        &format_tournament_subs_csv(elo_setting.get_tournament_sub_list()),
+    )?;
+    write_file(
+        character_path,
+        &format_characters_csv(elo_setting.get_character_list()),
+    )
+}
+
+pub fn load_records_csv(path: &str) -> Result<Vec<EloRecord>, String> {
+    read_file(path, parse_records_csv)
+}
+
+pub fn save_records_csv(path: &str, record_list: &[EloRecord]) -> Result<(), String> {
+    write_file(path, &format_records_csv(record_list))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::elo::{get_player_rating_history, EloRatingMode, EloRatingSetting};
+
+    fn split(line: &str) -> Vec<String> {
+        split_line(line).unwrap()
+    }
+
+    #[test]
+    fn split_line_plain_fields() {
+        assert_eq!(split("1,abc,"), vec!["1", "abc", ""]);
+    }
+
+    #[test]
+    fn split_line_quoted_fields() {
+        assert_eq!(split("1,\"a,b\",c"), vec!["1", "a,b", "c"]);
+        assert_eq!(split("\"say \"\"hi\"\"\",x"), vec!["say \"hi\"", "x"]);
+        assert_eq!(split("\"\",x"), vec!["", "x"]);
+    }
+
+    #[test]
+    fn split_line_quote_inside_unquoted_field() {
+        assert_eq!(split("a\"b,c"), vec!["a\"b", "c"]);
+    }
+
+    #[test]
+    fn split_line_unclosed_quote() {
+        assert!(split_line("1,\"abc").is_err());
+    }
+
+    #[test]
+    fn read_rows_joins_newlines_in_quotes() {
+        let rows = read_rows("id,name\n1,\"a\n\nb\"\n2,c\n", CHARACTER_HEADER).unwrap();
+        let rows: Vec<(usize, Vec<String>)> = rows
+            .into_iter()
+            .map(|(line_number, fields)| (line_number, fields.unwrap()))
+            .collect();
+        assert_eq!(
+            rows,
+            vec![
+                (2, vec!["1".to_string(), "a\n\nb".to_string()]),
+                (5, vec!["2".to_string(), "c".to_string()]),
+            ]
+        );
+    }
+
+    #[test]
+    fn unclosed_quote_at_end_is_reported_at_its_first_line() {
+        let error = match parse_characters_csv("id,name\n1,a\n2,\"b\n3,c\n") {
+            Ok(_) => panic!("エラーにならない"),
+            Err(e) => e,
+        };
+        assert_eq!(error, "3行目: 引用符が閉じていません");
+    }
+
+    #[test]
+    fn names_with_newlines_round_trip() {
+        let mut player = Player::new(1, "first\nsecond");
+        player.add_alias("x,\"y\"\nz");
+        let players =
+            parse_players_csv(&format_players_csv(&[player.clone(), Player::new(2, "b")])).unwrap();
+        assert_eq!(players.len(), 2);
+        assert_eq!(players[0].name, player.name);
+        assert_eq!(players[0].aliases, player.aliases);
+        assert_eq!(players[1].name, "b");
+    }
+
+    #[test]
+    fn escape_field_round_trip() {
+        let fields: Vec<String> = ["1", "a,b", "say \"hi\"", ""]
+            .iter()
+            .map(|f| f.to_string())
+            .collect();
+        assert_eq!(split(&format_line(&fields)), fields);
+    }
+
+    #[test]
+    fn read_rows_strips_bom_and_crlf() {
+        let rows = read_rows("\u{feff}id,name\r\n1,a\r\n\r\n2,b\r\n", CHARACTER_HEADER).unwrap();
+        let rows: Vec<(usize, Vec<String>)> = rows
+            .into_iter()
+            .map(|(line_number, fields)| (line_number, fields.unwrap()))
+            .collect();
+        assert_eq!(
+            rows,
+            vec![
+                (2, vec!["1".to_string(), "a".to_string()]),
+                (4, vec!["2".to_string(), "b".to_string()]),
+            ]
+        );
+    }
+
+    #[test]
+    fn read_rows_wrong_header() {
+        assert!(read_rows("id,title\n1,a\n", CHARACTER_HEADER).is_err());
+        assert!(read_rows("", CHARACTER_HEADER).is_err());
+    }
+
+    #[test]
+    fn parse_errors_have_line_numbers() {
+        let error = match parse_characters_csv("id,name\n1,a\n2\n3,c,d\n") {
+            Ok(_) => panic!("エラーにならない"),
+            Err(e) => e,
+        };
+        assert_eq!(
+            error,
+            "3行目: 列の数が 1 です（2 列にしてください）\n4行目: 列の数が 3 です（2 列にしてください）"
+        );
+    }
+
+    #[test]
+    fn aliases_round_trip() {
+        let mut player = Player::new(1, "main");
+        for alias in ["a|b", "c\\d", "e\\|f", "g"] {
+            player.add_alias(alias);
+        }
+        let players = parse_players_csv(&format_players_csv(&[player.clone()])).unwrap();
+        assert_eq!(players.len(), 1);
+        assert_eq!(players[0].name, player.name);
+        assert_eq!(players[0].aliases, player.aliases);
+    }
+
+    #[test]
+    fn aliases_keep_unknown_escape() {
+        assert_eq!(split_aliases("a\\b|c\\"), vec!["a\\b", "c\\"]);
+    }
+
+    #[test]
+    fn rating_history_has_key_columns() {
+        let elo_setting = EloSetting::new(
+            vec![Player::new(1, "a"), Player::new(2, "b")],
+            vec![Tournament {
+                id: 1,
+                name: "t".to_string(),
+                tier: TournamentTier::Weekly,
+            }],
+            vec![TournamentSub {
+                id: 1,
+                name: "s".to_string(),
+            }],
+            vec![Character {
+                id: 5,
+                name: "c".to_string(),
+            }],
+        );
+        let history = get_player_rating_history(
+            &elo_setting,
+            &[EloRecord::new(1, 1, 10, 1, 5, 2, 5, 1, 0)],
+            &EloRatingSetting {
+                rating_mode: EloRatingMode::PlayerCharacter,
+                ..EloRatingSetting::default()
+            },
+        )
+        .unwrap();
+        let text = format_rating_history_csv(&elo_setting, &history);
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(
+            lines[1],
+            "10,1,t,1,PlayerCharacter,1,a,5,c,2,b,1500.0,1508.0,8.0"
+        );
+        assert_eq!(
+            lines[2],
+            "10,1,t,1,PlayerCharacter,2,b,5,c,1,a,1500.0,1492.0,-8.0"
+        );
+    }
+}
@@ -1,10 +1,35 @@
+use crate::glicko::{get_player_glicko2_rating, Glicko2Setting};
+use serde::{Deserialize, Serialize};
 use std::cmp;
 use std::collections::HashMap;
+use std::fs;
+use std::str::FromStr;
 
 #[derive(Clone)]
 pub struct Tournament {
     pub id: u32,
     pub name: String,
+    pub tier: TournamentTier,
+}
+
+// 大会の格。EloRatingSetting::tier_multipliers で K に掛ける倍率を決める
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub enum TournamentTier {
+    Weekly,
+    Regional,
+    Major,
+}
+
+impl FromStr for TournamentTier {
+    type Err = String;
+    fn from_str(s: &str) -> Result<TournamentTier, String> {
+        match s {
+            "Weekly" => Ok(TournamentTier::Weekly),
+            "Regional" => Ok(TournamentTier::Regional),
+            "Major" => Ok(TournamentTier::Major),
+            _ => Err(format!("不明な大会の格: {}", s)),
+        }
+    }
 }
 
 #[derive(Clone)]
@@ -17,6 +42,31 @@ pub struct TournamentSub {
 pub struct Player {
     pub id: u32,
     pub name: String,
+    // name 以外の名前（大会ごとに違うタグなど）
+    pub aliases: Vec<String>,
+}
+
+impl Player {
+    pub fn new(id: u32, name: &str) -> Player {
+        Player {
+            id,
+            name: name.to_string(),
+            aliases: vec![],
+        }
+    }
+    // name か aliases のどれかと大文字小文字を区別せずに一致するか
+    pub fn has_name(&self, name: &str) -> bool {
+        let name = name.trim().to_lowercase();
+        std::iter::once(&self.name)
+            .chain(self.aliases.iter())
+            .any(|n| n.trim().to_lowercase() == name)
+    }
+    // すでにある名前なら何もしない
+    pub fn add_alias(&mut self, alias: &str) {
+        if !self.has_name(alias) {
+            self.aliases.push(alias.trim().to_string());
+        }
+    }
 }
 
 #[derive(Clone)]
@@ -25,11 +75,25 @@ pub struct Character {
     pub name: String,
 }
 
+#[derive(Clone)]
 pub struct EloSetting {
     player_list: Vec<Player>,
     tournament_list: Vec<Tournament>,
     tournament_sub_list: Vec<TournamentSub>,
     character_list: Vec<Character>,
+    // id → 一覧での位置。id が重複していれば先にあるものを使う
+    player_index: HashMap<u32, usize>,
+    tournament_index: HashMap<u32, usize>,
+    tournament_sub_index: HashMap<u32, usize>,
+    character_index: HashMap<u32, usize>,
+}
+
+fn create_index<T>(list: &[T], get_id: impl Fn(&T) -> u32) -> HashMap<u32, usize> {
+    let mut index_map: HashMap<u32, usize> = HashMap::with_capacity(list.len());
+    for (index, item) in list.iter().enumerate() {
+        index_map.entry(get_id(item)).or_insert(index);
+    }
+    index_map
 }
 
 impl EloSetting {
@@ -40,46 +104,113 @@ impl EloSetting {
         character_list: Vec<Character>,
     ) -> EloSetting {
         EloSetting {
+            player_index: create_index(&player_list, |p| p.id),
+            tournament_index: create_index(&tournament_list, |t| t.id),
+            tournament_sub_index: create_index(&tournament_sub_list, |t| t.id),
+            character_index: create_index(&character_list, |c| c.id),
             player_list,
             tournament_list,
             tournament_sub_list,
             character_list,
         }
     }
+    pub fn get_player_ids(&self) -> Vec<u32> {
+        self.player_list.iter().map(|p| p.id).collect()
+    }
+    pub fn get_player_list(&self) -> &[Player] {
+        &self.player_list
+    }
+    pub fn get_tournament_list(&self) -> &[Tournament] {
+        &self.tournament_list
+    }
+    pub fn get_tournament_sub_list(&self) -> &[TournamentSub] {
+        &self.tournament_sub_list
+    }
+    pub fn get_character_list(&self) -> &[Character] {
+        &self.character_list
+    }
     pub fn find_player(&self, id: u32) -> Option<&Player> {
-        self.player_list.iter().find(|p| p.id == id)
+        self.player_index.get(&id).map(|i| &self.player_list[*i])
     }
-    fn find_tournament(&self, id: u32) -> Option<&Tournament> {
-        self.tournament_list.iter().find(|p| p.id == id)
+    pub fn find_tournament(&self, id: u32) -> Option<&Tournament> {
+        self.tournament_index
+            .get(&id)
+            .map(|i| &self.tournament_list[*i])
     }
-    fn find_tournament_sub(&self, id: u32) -> Option<&TournamentSub> {
-        self.tournament_sub_list.iter().find(|p| p.id == id)
+    pub fn find_tournament_sub(&self, id: u32) -> Option<&TournamentSub> {
+        self.tournament_sub_index
+            .get(&id)
+            .map(|i| &self.tournament_sub_list[*i])
     }
-    fn find_character(&self, id: u32) -> Option<&Character> {
-        self.character_list.iter().find(|p| p.id == id)
+    pub fn find_character(&self, id: u32) -> Option<&Character> {
+        self.character_index
+            .get(&id)
+            .map(|i| &self.character_list[*i])
     }
-    fn create_rating_map(&self) -> HashMap<u32, f64> {
-        let mut rating_map: HashMap<u32, f64> = HashMap::new();
+    // 別名も含めて、大文字小文字を区別せずに探す。複数の選手が同じ名前を持っていればエラー
+    pub fn find_player_by_name(&self, name: &str) -> Result<&Player, String> {
+        let players: Vec<&Player> = self
+            .player_list
+            .iter()
+            .filter(|p| p.has_name(name))
+            .collect();
+        match players.as_slice() {
+            [player] => Ok(player),
+            [] => Err(format!("不明な選手: {}", name)),
+            _ => Err(format!(
+                "{} に一致する選手が複数いる: {}",
+                name,
+                players
+                    .iter()
+                    .map(|p| p.id.to_string())
+                    .collect::<Vec<String>>()
+                    .join(", ")
+            )),
+        }
+    }
+    pub fn add_player_alias(&mut self, player_id: u32, alias: &str) -> Result<(), String> {
+        let index = *self
+            .player_index
+            .get(&player_id)
+            .ok_or(format!("不明な選手 {}", player_id))?;
+        self.player_list[index].add_alias(alias);
+        Ok(())
+    }
+    fn remove_player(&mut self, player_id: u32) -> Option<Player> {
+        let index = *self.player_index.get(&player_id)?;
+        let player = self.player_list.remove(index);
+        self.player_index = create_index(&self.player_list, |p| p.id);
+        Some(player)
+    }
+    fn create_rating_map(&self, rating_setting: &EloRatingSetting) -> HashMap<EloRatingKey, f64> {
+        let mut rating_map: HashMap<EloRatingKey, f64> = HashMap::new();
         for p in self.player_list.iter() {
-            rating_map.insert(p.id, 1500_f64);
+            rating_map.insert(
+                EloRatingKey::Player(p.id),
+                rating_setting.get_initial_rating(p.id),
+            );
         }
         rating_map
     }
 }
 
+#[derive(Clone)]
 pub struct EloRecord {
-    date: u64,
-    tournament_id: u32,
-    tournament_sub_id: u32,
-    player_id: u32,
-    character_id: u32,
-    opponent_player_id: u32,
-    opponent_character_id: u32,
-    win_count: u32,
-    lose_count: u32,
+    pub date: u64,
+    pub tournament_id: u32,
+    pub tournament_sub_id: u32,
+    pub player_id: u32,
+    pub character_id: u32,
+    pub opponent_player_id: u32,
+    pub opponent_character_id: u32,
+    pub win_count: u32,
+    pub lose_count: u32,
+    // 1本ずつの勝敗を行われた順に（player 側から見て勝ちなら true）。わからなければ空
+    pub game_results: Vec<bool>,
 }
 
 impl EloRecord {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         tournament_id: u32,
         tournament_sub_id: u32,
@@ -101,68 +232,1105 @@ impl EloRecord {
             opponent_character_id,
             win_count,
             lose_count,
+            game_results: vec![],
+        }
+    }
+    // 勝敗の順がわかっているとき（EloSeriesMode::GameOrder で使う）
+    pub fn with_game_results(mut self, game_results: Vec<bool>) -> EloRecord {
+        self.game_results = game_results;
+        self
+    }
+}
+
+pub const DEFAULT_K: f64 = 16_f64;
+pub const DEFAULT_INITIAL_RATING: f64 = 1500_f64;
+
+// 何ごとにレートを持つか
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EloRatingMode {
+    // プレイヤーごと（キャラクターは見ない）
+    Player,
+    // プレイヤーとキャラクターの組ごと（サブキャラでの強さ）
+    PlayerCharacter,
+    // キャラクターごと（キャラランク用）。同キャラ戦は反映しない
+    Character,
+    // プレイヤーのレートとプレイヤー・キャラクターのレートの平均で勝率を出し、両方を更新する
+    PlayerAndCharacter,
+}
+
+impl FromStr for EloRatingMode {
+    type Err = String;
+    fn from_str(s: &str) -> Result<EloRatingMode, String> {
+        match s {
+            "Player" => Ok(EloRatingMode::Player),
+            "PlayerCharacter" => Ok(EloRatingMode::PlayerCharacter),
+            "Character" => Ok(EloRatingMode::Character),
+            "PlayerAndCharacter" => Ok(EloRatingMode::PlayerAndCharacter),
+            _ => Err(format!("不明なレートの単位: {}", s)),
+        }
+    }
+}
+
+impl EloRatingMode {
+    // (player 側のキー, opponent 側のキー)
+    fn get_rating_keys(&self, r: &EloRecord) -> (Vec<EloRatingKey>, Vec<EloRatingKey>) {
+        match self {
+            EloRatingMode::Player => (
+                vec![EloRatingKey::Player(r.player_id)],
+                vec![EloRatingKey::Player(r.opponent_player_id)],
+            ),
+            EloRatingMode::PlayerCharacter => (
+                vec![EloRatingKey::PlayerCharacter(r.player_id, r.character_id)],
+                vec![EloRatingKey::PlayerCharacter(
+                    r.opponent_player_id,
+                    r.opponent_character_id,
+                )],
+            ),
+            EloRatingMode::Character => (
+                vec![EloRatingKey::Character(r.character_id)],
+                vec![EloRatingKey::Character(r.opponent_character_id)],
+            ),
+            EloRatingMode::PlayerAndCharacter => (
+                vec![
+                    EloRatingKey::Player(r.player_id),
+                    EloRatingKey::PlayerCharacter(r.player_id, r.character_id),
+                ],
+                vec![
+                    EloRatingKey::Player(r.opponent_player_id),
+                    EloRatingKey::PlayerCharacter(r.opponent_player_id, r.opponent_character_id),
+                ],
+            ),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum EloRatingKey {
+    Player(u32),
+    // (player_id, character_id)
+    PlayerCharacter(u32, u32),
+    Character(u32),
+}
+
+// 1つのレコードを反映するときの K の決め方
+#[derive(Clone, Debug)]
+pub enum KFactor {
+    Fixed(f64),
+    // 対戦数が provisional_game_count に達するまでは provisional_k（暫定レートを早く動かす）
+    GamesPlayed {
+        provisional_k: f64,
+        k: f64,
+        provisional_game_count: u32,
+    },
+    // (レートの下限, K) を下限の昇順に並べる。レートが下限以上の帯のうち最も上の K
+    // どの下限にも届かなければ最初の K
+    RatingBand(Vec<(f64, f64)>),
+}
+
+impl KFactor {
+    pub fn get_k(&self, rating: f64, game_count: u32) -> f64 {
+        match self {
+            KFactor::Fixed(k) => *k,
+            KFactor::GamesPlayed {
+                provisional_k,
+                k,
+                provisional_game_count,
+            } => {
+                if game_count < *provisional_game_count {
+                    *provisional_k
+                } else {
+                    *k
+                }
+            }
+            KFactor::RatingBand(bands) => bands
+                .iter()
+                .rev()
+                .find(|(min_rating, _)| rating >= *min_rating)
+                .or(bands.first())
+                .map_or(DEFAULT_K, |(_, k)| *k),
+        }
+    }
+}
+
+// 1つのレコード（勝ち数と負け数）をどう反映するか
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EloSeriesMode {
+    // 1本ずつ、勝ちと負けを交互に並べてから余りの分を反映する
+    GameByGame,
+    // シリーズの勝敗で1回だけ反映する。変化量は本数差に応じて大きくする
+    Series,
+    // EloRecord::game_results があればその順に1本ずつ反映する。なければ GameByGame
+    GameOrder,
+}
+
+impl FromStr for EloSeriesMode {
+    type Err = String;
+    fn from_str(s: &str) -> Result<EloSeriesMode, String> {
+        match s {
+            "GameByGame" => Ok(EloSeriesMode::GameByGame),
+            "Series" => Ok(EloSeriesMode::Series),
+            "GameOrder" => Ok(EloSeriesMode::GameOrder),
+            _ => Err(format!("不明なシリーズの反映方法: {}", s)),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct EloRatingSetting {
+    pub rating_mode: EloRatingMode,
+    pub k_factor: KFactor,
+    // 大会の格ごとに K に掛ける倍率。ない格は 1 倍
+    pub tier_multipliers: HashMap<TournamentTier, f64>,
+    pub series_mode: EloSeriesMode,
+    // 初期レート。initial_ratings にないプレイヤーと、EloRatingKey::Character はこの値から始まる
+    pub initial_rating: f64,
+    // プレイヤーごとの初期レート（他のサーキットのレートや seed_by_placement で決めたもの）
+    // そのプレイヤーの EloRatingKey::PlayerCharacter もこの値から始まる
+    pub initial_ratings: HashMap<u32, f64>,
+    // None なら対戦が空いても何もしない
+    pub inactivity_policy: Option<InactivityPolicy>,
+}
+
+// 対戦が空いたときの扱い。期間はすべて EloRecord::date と同じ単位
+#[derive(Clone, Debug)]
+pub struct InactivityPolicy {
+    // 最後の対戦からこれより空くと非アクティブ（ランキングに載せない）
+    pub inactive_after: u64,
+    // 非アクティブになってから decay_period ごとに、initial_rating との差を decay_rate の割合だけ縮める
+    // 縮める先は initial_ratings ではなく全体の initial_rating にする
+    // initial_ratings は対戦前の見積もりなので、対戦が空いてわからなくなった分は全体の平均に寄せる
+    // decay_rate が 0 なら非アクティブの判定だけ
+    pub decay_period: u64,
+    pub decay_rate: f64,
+}
+
+impl InactivityPolicy {
+    pub fn is_inactive(&self, last_date: Option<u64>, date: u64) -> bool {
+        match last_date {
+            Some(last_date) => date.saturating_sub(last_date) > self.inactive_after,
+            None => true,
+        }
+    }
+    // last_date から date まで対戦がなかったときのレート
+    // 状態には縮める前のレートを残し、次に対戦したときと読み出すとき（get_decayed_rating_map）に縮める
+    pub fn get_decayed_rating(
+        &self,
+        rating: f64,
+        mean_rating: f64,
+        last_date: u64,
+        date: u64,
+    ) -> f64 {
+        let idle = date.saturating_sub(last_date);
+        if idle <= self.inactive_after || self.decay_period == 0 {
+            return rating;
+        }
+        let period_count = (idle - self.inactive_after) / self.decay_period;
+        mean_rating + (rating - mean_rating) * (1_f64 - self.decay_rate).powf(period_count as f64)
+    }
+}
+
+impl Default for EloRatingSetting {
+    fn default() -> Self {
+        EloRatingSetting {
+            rating_mode: EloRatingMode::Player,
+            k_factor: KFactor::Fixed(DEFAULT_K),
+            tier_multipliers: HashMap::new(),
+            series_mode: EloSeriesMode::GameByGame,
+            initial_rating: DEFAULT_INITIAL_RATING,
+            initial_ratings: HashMap::new(),
+            inactivity_policy: None,
+        }
+    }
+}
+
+impl EloRatingSetting {
+    pub fn get_initial_rating(&self, player_id: u32) -> f64 {
+        self.initial_ratings
+            .get(&player_id)
+            .copied()
+            .unwrap_or(self.initial_rating)
+    }
+    pub fn get_key_initial_rating(&self, key: &EloRatingKey) -> f64 {
+        match key {
+            EloRatingKey::Player(player_id) | EloRatingKey::PlayerCharacter(player_id, _) => {
+                self.get_initial_rating(*player_id)
+            }
+            EloRatingKey::Character(_) => self.initial_rating,
+        }
+    }
+    pub fn get_tier_multiplier(&self, tournament: &Tournament) -> f64 {
+        self.tier_multipliers
+            .get(&tournament.tier)
+            .copied()
+            .unwrap_or(1_f64)
+    }
+}
+
+// レコードを反映した状態。ファイルに保存しておけば、次は新しいレコードだけを反映すればよい
+// 保存したときと同じ EloRatingSetting で続きを反映すること
+#[derive(Clone, Debug)]
+pub struct EloRatingState {
+    pub rating_map: HashMap<EloRatingKey, f64>,
+    // キーごとの対戦数（KFactor::GamesPlayed 用）。勝ち負け1本ずつを1と数える
+    pub game_count_map: HashMap<EloRatingKey, u32>,
+    // キーごとに最後に対戦した日付
+    pub last_date_map: HashMap<EloRatingKey, u64>,
+    // 反映したレコードのうち最も新しい日付
+    pub last_date: Option<u64>,
+    // 最後に反映したレコードの並び順 (date, tournament_id, tournament_sub_id)（get_record_order）
+    pub last_order: Option<(u64, u32, u32)>,
+}
+
+impl EloRatingState {
+    pub fn new(elo_setting: &EloSetting, rating_setting: &EloRatingSetting) -> EloRatingState {
+        EloRatingState {
+            rating_map: elo_setting.create_rating_map(rating_setting),
+            game_count_map: HashMap::new(),
+            last_date_map: HashMap::new(),
+            last_date: None,
+            last_order: None,
+        }
+    }
+}
+
+// EloRatingState の保存形式（JSON）。キーが数値や組なので一覧にして持つ
+#[derive(Serialize, Deserialize)]
+pub struct EloRatingStateFile {
+    pub last_date: Option<u64>,
+    pub last_order: Option<(u64, u32, u32)>,
+    pub entries: Vec<EloRatingStateEntry>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct EloRatingStateEntry {
+    pub key: EloRatingKey,
+    pub rating: f64,
+    pub game_count: u32,
+    pub last_date: Option<u64>,
+}
+
+impl EloRatingStateFile {
+    // 差分を見やすいようにキーの順に並べる
+    pub fn from_state(state: &EloRatingState) -> EloRatingStateFile {
+        let mut entries: Vec<EloRatingStateEntry> = state
+            .rating_map
+            .iter()
+            .map(|(key, rating)| EloRatingStateEntry {
+                key: *key,
+                rating: *rating,
+                game_count: state.game_count_map.get(key).copied().unwrap_or(0),
+                last_date: state.last_date_map.get(key).copied(),
+            })
+            .collect();
+        entries.sort_by_key(|entry| entry.key);
+        EloRatingStateFile {
+            last_date: state.last_date,
+            last_order: state.last_order,
+            entries,
+        }
+    }
+    pub fn into_state(self) -> EloRatingState {
+        let mut state = EloRatingState {
+            rating_map: HashMap::new(),
+            game_count_map: HashMap::new(),
+            last_date_map: HashMap::new(),
+            last_date: self.last_date,
+            last_order: self.last_order,
+        };
+        for entry in self.entries.into_iter() {
+            state.rating_map.insert(entry.key, entry.rating);
+            if entry.game_count != 0 {
+                state.game_count_map.insert(entry.key, entry.game_count);
+            }
+            if let Some(last_date) = entry.last_date {
+                state.last_date_map.insert(entry.key, last_date);
+            }
+        }
+        state
+    }
+}
+
+pub fn save_rating_state(path: &str, state: &EloRatingState) -> Result<(), String> {
+    let text = serde_json::to_string_pretty(&EloRatingStateFile::from_state(state))
+        .map_err(|e| e.to_string())?;
+    fs::write(path, text).map_err(|e| format!("{}: {}", path, e))
+}
+
+pub fn load_rating_state(path: &str) -> Result<EloRatingState, String> {
+    let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+    let state_file: EloRatingStateFile =
+        serde_json::from_str(&text).map_err(|e| format!("{}: {}", path, e))?;
+    Ok(state_file.into_state())
+}
+
+// 登録されていないプレイヤー、大会、サブ大会、キャラクターを参照していればエラー
+pub fn validate_record(elo_setting: &EloSetting, r: &EloRecord) -> Result<(), String> {
+    if elo_setting.find_tournament(r.tournament_id).is_none() {
+        return Err(format!("未登録の大会: {}", r.tournament_id));
+    }
+    if elo_setting
+        .find_tournament_sub(r.tournament_sub_id)
+        .is_none()
+    {
+        return Err(format!("未登録のサブ大会: {}", r.tournament_sub_id));
+    }
+    for player_id in [r.player_id, r.opponent_player_id] {
+        if elo_setting.find_player(player_id).is_none() {
+            return Err(format!("未登録のプレイヤー: {}", player_id));
+        }
+    }
+    for character_id in [r.character_id, r.opponent_character_id] {
+        if elo_setting.find_character(character_id).is_none() {
+            return Err(format!("未登録のキャラクター: {}", character_id));
         }
     }
+    if r.player_id == r.opponent_player_id {
+        return Err(format!("同じプレイヤー同士の対戦: {}", r.player_id));
+    }
+    // 勝敗のないレコードは EloSeriesMode::Series で引き分けとして扱われてしまう
+    if r.win_count == 0 && r.lose_count == 0 {
+        return Err("勝ち数と負け数がどちらも 0".to_string());
+    }
+    if !r.game_results.is_empty() {
+        let win_count = r.game_results.iter().filter(|win_flag| **win_flag).count() as u32;
+        let lose_count = r.game_results.len() as u32 - win_count;
+        if win_count != r.win_count || lose_count != r.lose_count {
+            return Err(format!(
+                "勝敗の順 {}勝{}敗 が勝ち数、負け数 {}勝{}敗 と合わない",
+                win_count, lose_count, r.win_count, r.lose_count
+            ));
+        }
+    }
+    Ok(())
 }
 
-const K: f64 = 16_f64;
+// すべてのレコードを検証し、日付順（同じ日付なら大会、サブ大会の順、それも同じなら元の順）に並べる
+// 不正なレコードがあれば、元の並びでの番号を付けてすべて報告する
+pub fn sort_and_validate_records<'a>(
+    elo_setting: &EloSetting,
+    record_list: &'a [EloRecord],
+) -> Result<Vec<&'a EloRecord>, String> {
+    let errors: Vec<String> = record_list
+        .iter()
+        .enumerate()
+        .filter_map(|(index, r)| {
+            validate_record(elo_setting, r)
+                .err()
+                .map(|e| format!("レコード{}: {}", index, e))
+        })
+        .collect();
+    if !errors.is_empty() {
+        return Err(errors.join("\n"));
+    }
+    let mut sorted_record_list: Vec<&EloRecord> = record_list.iter().collect();
+    sorted_record_list.sort_by_key(|r| get_record_order(r));
+    Ok(sorted_record_list)
+}
+
+// レコードを反映する順 (date, tournament_id, tournament_sub_id)
+pub fn get_record_order(r: &EloRecord) -> (u64, u32, u32) {
+    (r.date, r.tournament_id, r.tournament_sub_id)
+}
 
+// プレイヤーごとのレートだけを取り出す（rating_mode が Player か PlayerAndCharacter のとき）
 pub fn get_player_rating(
     elo_setting: EloSetting,
     record_list: Vec<EloRecord>,
+    rating_setting: &EloRatingSetting,
+) -> Result<HashMap<u32, f64>, String> {
+    let rating_map = get_rating(&elo_setting, &record_list, rating_setting)?;
+    Ok(rating_map
+        .into_iter()
+        .filter_map(|(key, rating)| match key {
+            EloRatingKey::Player(player_id) => Some((player_id, rating)),
+            _ => None,
+        })
+        .collect())
+}
+
+// get_player_rating_by_system で選べるレーティング方式
+#[derive(Clone, Debug)]
+pub enum RatingSystem {
+    Elo(EloRatingSetting),
+    Glicko2(Glicko2Setting),
+}
+
+// deviation, volatility は Glicko-2 のときだけ
+#[derive(Clone, Copy, Debug)]
+pub struct PlayerRating {
+    pub rating: f64,
+    pub deviation: Option<f64>,
+    pub volatility: Option<f64>,
+}
+
+pub fn get_player_rating_by_system(
+    elo_setting: EloSetting,
+    record_list: Vec<EloRecord>,
+    rating_system: &RatingSystem,
+) -> Result<HashMap<u32, PlayerRating>, String> {
+    match rating_system {
+        RatingSystem::Elo(rating_setting) => {
+            Ok(get_player_rating(elo_setting, record_list, rating_setting)?
+                .into_iter()
+                .map(|(player_id, rating)| {
+                    (
+                        player_id,
+                        PlayerRating {
+                            rating,
+                            deviation: None,
+                            volatility: None,
+                        },
+                    )
+                })
+                .collect())
+        }
+        RatingSystem::Glicko2(glicko2_setting) => {
+            Ok(
+                get_player_glicko2_rating(&elo_setting, &record_list, glicko2_setting)?
+                    .into_iter()
+                    .map(|(player_id, glicko2_rating)| {
+                        (
+                            player_id,
+                            PlayerRating {
+                                rating: glicko2_rating.rating,
+                                deviation: Some(glicko2_rating.deviation),
+                                volatility: Some(glicko2_rating.volatility),
+                            },
+                        )
+                    })
+                    .collect(),
+            )
+        }
+    }
+}
+
+// rating_setting.rating_mode に応じたキーでレートを計算する
+// キーは EloRatingSetting::get_key_initial_rating から始まる（キャラクターを含むキーは最初に出てきたとき）
+// 最後の対戦から後に空いた分は縮めない。ある時点のレートは get_decayed_rating_map で出す
+pub fn get_rating(
+    elo_setting: &EloSetting,
+    record_list: &[EloRecord],
+    rating_setting: &EloRatingSetting,
+) -> Result<HashMap<EloRatingKey, f64>, String> {
+    let mut state = EloRatingState::new(elo_setting, rating_setting);
+    apply_records(&mut state, elo_setting, record_list, rating_setting)?;
+    Ok(state.rating_map)
+}
+
+// 保存しておいた状態に新しいレコードだけを反映する
+// 最後に反映したレコードと同じか前の並び順（get_record_order）のレコードがあればエラー
+// 同じ並び順のレコードは、反映済みのものの重複かどうか区別できず、全体を反映し直したときと順が変わることもあるため
+// 状態にないプレイヤー（あとから追加したプレイヤー）は EloRatingSetting::get_initial_rating から始まる
+pub fn apply_records(
+    state: &mut EloRatingState,
+    elo_setting: &EloSetting,
+    record_list: &[EloRecord],
+    rating_setting: &EloRatingSetting,
+) -> Result<(), String> {
+    let sorted_record_list = sort_and_validate_records(elo_setting, record_list)?;
+    if let Some(r) = sorted_record_list.first() {
+        let order = get_record_order(r);
+        if let Some(last_order) = state.last_order {
+            if order <= last_order {
+                return Err(format!(
+                    "反映済みの (日付, 大会, サブ大会) {:?} より後でないレコードがある: {:?}",
+                    last_order, order
+                ));
+            }
+        }
+    }
+    for (key, rating) in elo_setting.create_rating_map(rating_setting).into_iter() {
+        state.rating_map.entry(key).or_insert(rating);
+    }
+    replay_records(
+        state,
+        elo_setting,
+        &sorted_record_list,
+        rating_setting,
+        |_, _, _| {},
+    );
+    Ok(())
+}
+
+// from_player_id の選手を into_player_id の選手にまとめて、すべてのレコードからレートを計算し直す
+// from_player_id の名前と別名は into_player_id の別名になる
+// 2人の間のレコードは同じ選手同士の対戦になるので取り除く
+// 反映済みの EloRatingState は使えなくなるので、計算し直したものを返す
+// エラーのときは elo_setting と record_list を変えない
+pub fn merge_players(
+    elo_setting: &mut EloSetting,
+    record_list: &mut Vec<EloRecord>,
+    from_player_id: u32,
+    into_player_id: u32,
+    rating_setting: &EloRatingSetting,
+) -> Result<EloRatingState, String> {
+    if from_player_id == into_player_id {
+        return Err(format!("同じ選手はまとめられない: {}", from_player_id));
+    }
+    if elo_setting.find_player(into_player_id).is_none() {
+        return Err(format!("不明な選手 {}", into_player_id));
+    }
+    // 写しをまとめて、計算し直せたときだけ戻す
+    let mut merged_setting = elo_setting.clone();
+    let from_player = merged_setting
+        .remove_player(from_player_id)
+        .ok_or(format!("不明な選手 {}", from_player_id))?;
+    for name in std::iter::once(&from_player.name).chain(from_player.aliases.iter()) {
+        merged_setting.add_player_alias(into_player_id, name)?;
+    }
+    let mut merged_record_list: Vec<EloRecord> = record_list
+        .iter()
+        .filter(|r| {
+            let players = [r.player_id, r.opponent_player_id];
+            !(players.contains(&from_player_id) && players.contains(&into_player_id))
+        })
+        .cloned()
+        .collect();
+    for r in merged_record_list.iter_mut() {
+        if r.player_id == from_player_id {
+            r.player_id = into_player_id;
+        }
+        if r.opponent_player_id == from_player_id {
+            r.opponent_player_id = into_player_id;
+        }
+    }
+
+    let mut state = EloRatingState::new(&merged_setting, rating_setting);
+    apply_records(
+        &mut state,
+        &merged_setting,
+        &merged_record_list,
+        rating_setting,
+    )?;
+    *elo_setting = merged_setting;
+    *record_list = merged_record_list;
+    Ok(state)
+}
+
+// あるプレイヤーのキャラクターごとのレート（レートの高い順）
+pub fn get_player_character_ratings(
+    rating_map: &HashMap<EloRatingKey, f64>,
+    player_id: u32,
+) -> Vec<(u32, f64)> {
+    let mut ratings: Vec<(u32, f64)> = rating_map
+        .iter()
+        .filter_map(|(key, rating)| match key {
+            EloRatingKey::PlayerCharacter(p, character_id) if *p == player_id => {
+                Some((*character_id, *rating))
+            }
+            _ => None,
+        })
+        .collect();
+    ratings.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0)));
+    ratings
+}
+
+// 最初の大会の順位 (player_id, 順位) から初期レートを決める（EloRatingSetting::initial_ratings 用）
+// 1位を best_rating、最も下の順位を worst_rating として、順位の log2 で間を割り振る
+// トーナメントの順位（1, 2, 3, 4, 5, 7, 9, 13, ...）は倍々に増えるので、同じ勝ち数の差が同じレート差になる
+pub fn seed_by_placement(
+    placements: &[(u32, u32)],
+    best_rating: f64,
+    worst_rating: f64,
 ) -> HashMap<u32, f64> {
-    let mut rating_map = elo_setting.create_rating_map();
-    for r in record_list.iter() {
-        let player_rating = rating_map.get(&r.player_id);
-        if player_rating.is_none() {
-            continue;
+    let worst_placement = placements.iter().map(|(_, placement)| *placement).max();
+    let log_worst_placement = worst_placement.map_or(0_f64, |p| (p.max(1) as f64).log2());
+    placements
+        .iter()
+        .map(|(player_id, placement)| {
+            let rating = if log_worst_placement == 0_f64 {
+                best_rating
+            } else {
+                let log_placement = ((*placement).max(1) as f64).log2();
+                best_rating - log_placement / log_worst_placement * (best_rating - worst_rating)
+            };
+            (*player_id, rating)
+        })
+        .collect()
+}
+
+// キャラクターのレートの高い順（EloRatingMode::Character で計算したもの）
+pub fn get_character_tier_list(rating_map: &HashMap<EloRatingKey, f64>) -> Vec<(u32, f64)> {
+    let mut ratings: Vec<(u32, f64)> = rating_map
+        .iter()
+        .filter_map(|(key, rating)| match key {
+            EloRatingKey::Character(character_id) => Some((*character_id, *rating)),
+            _ => None,
+        })
+        .collect();
+    ratings.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0)));
+    ratings
+}
+
+// 1つのレコードを反映したときのキー1つ分のレートの変化
+// player_id は key のレートでそのレコードを戦ったプレイヤー（EloRatingMode::Character でも入る）
+pub struct EloRatingChange {
+    pub date: u64,
+    pub tournament_id: u32,
+    pub tournament_sub_id: u32,
+    pub key: EloRatingKey,
+    pub player_id: u32,
+    pub opponent_player_id: u32,
+    pub rating_before: f64,
+    pub rating_after: f64,
+}
+
+impl EloRatingChange {
+    pub fn get_delta(&self) -> f64 {
+        self.rating_after - self.rating_before
+    }
+}
+
+// get_player_rating と同じ順にレコードを反映し、レコードごとに更新したキーの変化を残す
+// 1つのレコードから player_id 側のキー、opponent_player_id 側のキーの順にできる
+// （EloRatingMode::PlayerAndCharacter では1人あたり Player と PlayerCharacter の2件）
+pub fn get_player_rating_history(
+    elo_setting: &EloSetting,
+    record_list: &[EloRecord],
+    rating_setting: &EloRatingSetting,
+) -> Result<Vec<EloRatingChange>, String> {
+    let sorted_record_list = sort_and_validate_records(elo_setting, record_list)?;
+    let mut state = EloRatingState::new(elo_setting, rating_setting);
+    let mut history: Vec<EloRatingChange> = Vec::with_capacity(sorted_record_list.len() * 2);
+    replay_records(
+        &mut state,
+        elo_setting,
+        &sorted_record_list,
+        rating_setting,
+        |r, player_changes, opponent_player_changes| {
+            for (player_id, opponent_player_id, changes) in [
+                (r.player_id, r.opponent_player_id, player_changes),
+                (r.opponent_player_id, r.player_id, opponent_player_changes),
+            ] {
+                for (key, rating_before, rating_after) in changes.iter() {
+                    history.push(EloRatingChange {
+                        date: r.date,
+                        tournament_id: r.tournament_id,
+                        tournament_sub_id: r.tournament_sub_id,
+                        key: *key,
+                        player_id,
+                        opponent_player_id,
+                        rating_before: *rating_before,
+                        rating_after: *rating_after,
+                    });
+                }
+            }
+        },
+    );
+    Ok(history)
+}
+
+// as_of の時点でのレート。inactivity_policy があれば、最後の対戦から as_of まで空いた分を縮める
+pub fn get_decayed_rating_map(
+    state: &EloRatingState,
+    rating_setting: &EloRatingSetting,
+    as_of: u64,
+) -> HashMap<EloRatingKey, f64> {
+    let policy = match rating_setting.inactivity_policy {
+        Some(ref policy) => policy,
+        None => return state.rating_map.clone(),
+    };
+    state
+        .rating_map
+        .iter()
+        .map(|(key, rating)| {
+            let rating = match state.last_date_map.get(key) {
+                Some(last_date) => policy.get_decayed_rating(
+                    *rating,
+                    rating_setting.initial_rating,
+                    *last_date,
+                    as_of,
+                ),
+                None => *rating,
+            };
+            (*key, rating)
+        })
+        .collect()
+}
+
+// as_of の時点でのプレイヤーのランキング（レートの高い順）。レートは get_decayed_rating_map で縮めたもの
+// inactivity_policy があれば非アクティブなプレイヤーを除く。なければ1度でも対戦したプレイヤー
+pub fn get_leaderboard(
+    state: &EloRatingState,
+    rating_setting: &EloRatingSetting,
+    as_of: u64,
+) -> Vec<(u32, f64)> {
+    let mut leaderboard: Vec<(u32, f64)> = get_decayed_rating_map(state, rating_setting, as_of)
+        .into_iter()
+        .filter_map(|(key, rating)| match key {
+            EloRatingKey::Player(player_id) => Some((key, player_id, rating)),
+            _ => None,
+        })
+        .filter(|(key, _, _)| {
+            let last_date = state.last_date_map.get(key).copied();
+            match rating_setting.inactivity_policy {
+                Some(ref policy) => !policy.is_inactive(last_date, as_of),
+                None => last_date.is_some(),
+            }
+        })
+        .map(|(_, player_id, rating)| (player_id, rating))
+        .collect();
+    leaderboard.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0)));
+    leaderboard
+}
+
+// 1人のプレイヤーの EloRatingKey::Player のレートの推移（グラフ用）
+// rating_mode が Player か PlayerAndCharacter でなければ空
+pub fn get_player_timeline(history: &[EloRatingChange], player_id: u32) -> Vec<&EloRatingChange> {
+    history
+        .iter()
+        .filter(|c| c.key == EloRatingKey::Player(player_id))
+        .collect()
+}
+
+// 大会ごとの EloRatingKey::Player の (大会前のレート, 大会後のレート) をプレイヤーごとにまとめる
+// 結果はプレイヤーが大会で最初に試合をした順
+pub fn get_tournament_rating_changes(
+    history: &[EloRatingChange],
+    tournament_id: u32,
+) -> Vec<(u32, f64, f64)> {
+    let mut changes: Vec<(u32, f64, f64)> = vec![];
+    for c in history
+        .iter()
+        .filter(|c| c.tournament_id == tournament_id && c.key == EloRatingKey::Player(c.player_id))
+    {
+        match changes
+            .iter_mut()
+            .find(|(player_id, _, _)| *player_id == c.player_id)
+        {
+            Some((_, _, rating_after)) => *rating_after = c.rating_after,
+            None => changes.push((c.player_id, c.rating_before, c.rating_after)),
+        }
+    }
+    changes
+}
+
+// レコードを反映する間の状態。キーを連番にして、キーごとの値を Vec で持つ
+struct DenseRatingState {
+    keys: Vec<EloRatingKey>,
+    key_index: HashMap<EloRatingKey, usize>,
+    ratings: Vec<f64>,
+    game_counts: Vec<Option<u32>>,
+    last_dates: Vec<Option<u64>>,
+}
+
+impl DenseRatingState {
+    fn new(state: &EloRatingState) -> DenseRatingState {
+        let mut dense_state = DenseRatingState {
+            keys: Vec::with_capacity(state.rating_map.len()),
+            key_index: HashMap::with_capacity(state.rating_map.len()),
+            ratings: Vec::with_capacity(state.rating_map.len()),
+            game_counts: Vec::with_capacity(state.rating_map.len()),
+            last_dates: Vec::with_capacity(state.rating_map.len()),
+        };
+        for (key, rating) in state.rating_map.iter() {
+            dense_state.push(
+                *key,
+                *rating,
+                state.game_count_map.get(key).copied(),
+                state.last_date_map.get(key).copied(),
+            );
+        }
+        dense_state
+    }
+    fn push(
+        &mut self,
+        key: EloRatingKey,
+        rating: f64,
+        game_count: Option<u32>,
+        last_date: Option<u64>,
+    ) -> usize {
+        let index = self.keys.len();
+        self.keys.push(key);
+        self.key_index.insert(key, index);
+        self.ratings.push(rating);
+        self.game_counts.push(game_count);
+        self.last_dates.push(last_date);
+        index
+    }
+    // まだないキーは initial_rating で加える
+    fn get_index(&mut self, key: EloRatingKey, initial_rating: f64) -> usize {
+        match self.key_index.get(&key) {
+            Some(index) => *index,
+            None => self.push(key, initial_rating, None, None),
+        }
+    }
+    fn write_to(self, state: &mut EloRatingState) {
+        for (index, key) in self.keys.into_iter().enumerate() {
+            state.rating_map.insert(key, self.ratings[index]);
+            if let Some(game_count) = self.game_counts[index] {
+                state.game_count_map.insert(key, game_count);
+            }
+            if let Some(last_date) = self.last_dates[index] {
+                state.last_date_map.insert(key, last_date);
+            }
         }
-        let opponent_player_rating = rating_map.get(&r.opponent_player_id);
-        if opponent_player_rating.is_none() {
+    }
+}
+
+// 1つのキーのレートの変化 (キー, 前のレート, 後のレート)
+type EloKeyChange = (EloRatingKey, f64, f64);
+
+// 並べ替え済みのレコードを順に state に反映する
+// on_update にはレートを更新したレコードと、player 側、opponent 側のキーごとの変化を渡す
+fn replay_records(
+    state: &mut EloRatingState,
+    elo_setting: &EloSetting,
+    sorted_record_list: &[&EloRecord],
+    rating_setting: &EloRatingSetting,
+    mut on_update: impl FnMut(&EloRecord, &[EloKeyChange], &[EloKeyChange]),
+) {
+    let mut dense_state = DenseRatingState::new(state);
+    let mut player_indices: Vec<usize> = vec![];
+    let mut opponent_player_indices: Vec<usize> = vec![];
+    let mut player_changes: Vec<EloKeyChange> = vec![];
+    let mut opponent_player_changes: Vec<EloKeyChange> = vec![];
+    for r in sorted_record_list.iter() {
+        state.last_date = state.last_date.max(Some(r.date));
+        state.last_order = state.last_order.max(Some(get_record_order(r)));
+        let (player_keys, opponent_player_keys) = rating_setting.rating_mode.get_rating_keys(r);
+        // 両者のキーが同じ（同キャラ戦の EloRatingMode::Character）なら何もしない
+        if player_keys == opponent_player_keys {
             continue;
         }
-        let mut player_rating = player_rating.unwrap().to_owned();
-        let mut opponent_player_rating = opponent_player_rating.unwrap().to_owned();
-        let win_lose_pair_count = cmp::min(r.win_count, r.lose_count);
-        let win_lose_over_count = cmp::max(r.win_count, r.lose_count) - win_lose_pair_count;
-        let win_flag = r.win_count >= r.lose_count;
-
-        for _ in 0..win_lose_pair_count {
-            if win_flag {
-                // ○の処理
-                (player_rating, opponent_player_rating) =
-                    update_rating(&player_rating, &opponent_player_rating, &win_flag);
-                // ×の処理
-                (player_rating, opponent_player_rating) =
-                    update_rating(&player_rating, &opponent_player_rating, &!win_flag);
-            } else {
-                // ×の処理
-                (player_rating, opponent_player_rating) =
-                    update_rating(&player_rating, &opponent_player_rating, &!win_flag);
-                // ○の処理
-                (player_rating, opponent_player_rating) =
-                    update_rating(&player_rating, &opponent_player_rating, &win_flag);
+        player_indices.clear();
+        opponent_player_indices.clear();
+        for (keys, indices) in [
+            (player_keys, &mut player_indices),
+            (opponent_player_keys, &mut opponent_player_indices),
+        ] {
+            for key in keys.into_iter() {
+                indices
+                    .push(dense_state.get_index(key, rating_setting.get_key_initial_rating(&key)));
+            }
+        }
+        // 空いていた分を先に縮める
+        if let Some(ref policy) = rating_setting.inactivity_policy {
+            for index in player_indices.iter().chain(opponent_player_indices.iter()) {
+                if let Some(last_date) = dense_state.last_dates[*index] {
+                    dense_state.ratings[*index] = policy.get_decayed_rating(
+                        dense_state.ratings[*index],
+                        rating_setting.initial_rating,
+                        last_date,
+                        r.date,
+                    );
+                }
             }
         }
-        for _ in 0..win_lose_over_count {
+        let tier_multiplier = elo_setting
+            .find_tournament(r.tournament_id)
+            .map_or(1_f64, |tournament| {
+                rating_setting.get_tier_multiplier(tournament)
+            });
+        for (indices, changes) in [
+            (&player_indices, &mut player_changes),
+            (&opponent_player_indices, &mut opponent_player_changes),
+        ] {
+            changes.clear();
+            changes.extend(indices.iter().map(|index| {
+                let rating = dense_state.ratings[*index];
+                (dense_state.keys[*index], rating, rating)
+            }));
+        }
+        update_rating_state(
+            &mut dense_state,
+            r,
+            &player_indices,
+            &opponent_player_indices,
+            tier_multiplier,
+            rating_setting,
+        );
+        for (indices, changes) in [
+            (&player_indices, &mut player_changes),
+            (&opponent_player_indices, &mut opponent_player_changes),
+        ] {
+            for (change, index) in changes.iter_mut().zip(indices.iter()) {
+                change.2 = dense_state.ratings[*index];
+            }
+        }
+        on_update(r, &player_changes, &opponent_player_changes);
+    }
+    dense_state.write_to(state);
+}
+
+// レコードを1つ反映する
+// キーが複数あるときは平均をそのプレイヤーのレートとして扱い、変化量をそれぞれのキーに足す
+// K はレコードを反映する前のレートと、最初のキーの対戦数から両者それぞれに決める
+fn update_rating_state(
+    dense_state: &mut DenseRatingState,
+    r: &EloRecord,
+    player_indices: &[usize],
+    opponent_player_indices: &[usize],
+    tier_multiplier: f64,
+    rating_setting: &EloRatingSetting,
+) {
+    let get_mean_rating = |ratings: &[f64], indices: &[usize]| {
+        indices.iter().map(|index| ratings[*index]).sum::<f64>() / indices.len() as f64
+    };
+    let player_rating_before = get_mean_rating(&dense_state.ratings, player_indices);
+    let opponent_player_rating_before =
+        get_mean_rating(&dense_state.ratings, opponent_player_indices);
+    let get_k = |rating: f64, index: usize| {
+        let game_count = dense_state.game_counts[index].unwrap_or(0);
+        rating_setting.k_factor.get_k(rating, game_count) * tier_multiplier
+    };
+    let k_factor = (
+        get_k(player_rating_before, player_indices[0]),
+        get_k(opponent_player_rating_before, opponent_player_indices[0]),
+    );
+    let (player_rating, opponent_player_rating) = match rating_setting.series_mode {
+        EloSeriesMode::GameByGame => play_record(
+            player_rating_before,
+            opponent_player_rating_before,
+            r,
+            &k_factor,
+        ),
+        EloSeriesMode::Series => play_series(
+            player_rating_before,
+            opponent_player_rating_before,
+            r,
+            &k_factor,
+        ),
+        EloSeriesMode::GameOrder if !r.game_results.is_empty() => play_game_results(
+            player_rating_before,
+            opponent_player_rating_before,
+            &r.game_results,
+            &k_factor,
+        ),
+        EloSeriesMode::GameOrder => play_record(
+            player_rating_before,
+            opponent_player_rating_before,
+            r,
+            &k_factor,
+        ),
+    };
+    for (indices, delta) in [
+        (player_indices, player_rating - player_rating_before),
+        (
+            opponent_player_indices,
+            opponent_player_rating - opponent_player_rating_before,
+        ),
+    ] {
+        for index in indices.iter() {
+            dense_state.ratings[*index] += delta;
+            let game_count = dense_state.game_counts[*index].get_or_insert(0);
+            *game_count += r.win_count + r.lose_count;
+            dense_state.last_dates[*index] = Some(r.date);
+        }
+    }
+}
+
+// 勝ち数と負け数を1本ずつ update_rating にかける
+fn play_record(
+    mut player_rating: f64,
+    mut opponent_player_rating: f64,
+    r: &EloRecord,
+    k_factor: &(f64, f64),
+) -> (f64, f64) {
+    let win_lose_pair_count = cmp::min(r.win_count, r.lose_count);
+    let win_lose_over_count = cmp::max(r.win_count, r.lose_count) - win_lose_pair_count;
+    let win_flag = r.win_count >= r.lose_count;
+
+    for _ in 0..win_lose_pair_count {
+        if win_flag {
+            // ○の処理
             (player_rating, opponent_player_rating) =
-                update_rating(&player_rating, &opponent_player_rating, &win_flag);
+                update_rating(&player_rating, &opponent_player_rating, &win_flag, k_factor);
+            // ×の処理
+            (player_rating, opponent_player_rating) = update_rating(
+                &player_rating,
+                &opponent_player_rating,
+                &!win_flag,
+                k_factor,
+            );
+        } else {
+            // ×の処理
+            (player_rating, opponent_player_rating) = update_rating(
+                &player_rating,
+                &opponent_player_rating,
+                &!win_flag,
+                k_factor,
+            );
+            // ○の処理
+            (player_rating, opponent_player_rating) =
+                update_rating(&player_rating, &opponent_player_rating, &win_flag, k_factor);
         }
-        rating_map.insert(r.player_id, player_rating);
-        rating_map.insert(r.opponent_player_id, opponent_player_rating);
     }
-    rating_map
+    for _ in 0..win_lose_over_count {
+        (player_rating, opponent_player_rating) =
+            update_rating(&player_rating, &opponent_player_rating, &win_flag, k_factor);
+    }
+    (player_rating, opponent_player_rating)
+}
+
+// 勝敗を行われた順に update_rating にかける
+fn play_game_results(
+    mut player_rating: f64,
+    mut opponent_player_rating: f64,
+    game_results: &[bool],
+    k_factor: &(f64, f64),
+) -> (f64, f64) {
+    for win_flag in game_results.iter() {
+        (player_rating, opponent_player_rating) =
+            update_rating(&player_rating, &opponent_player_rating, win_flag, k_factor);
+    }
+    (player_rating, opponent_player_rating)
 }
 
-pub fn update_rating(a_rate: &f64, b_rate: &f64, a_win: &bool) -> (f64, f64) {
+// シリーズの勝敗（同数なら引き分け）を1回の結果として反映する
+// 変化量には本数差 d に応じた ln(d + 1) / ln(2) を掛ける（1本差なら 1 倍）
+// 格上が勝ったときは倍率を小さく、格下が勝ったときは大きくして、本数差の補正で格上のレートが膨らみ続けないようにする
+fn play_series(
+    player_rating: f64,
+    opponent_player_rating: f64,
+    r: &EloRecord,
+    k_factor: &(f64, f64),
+) -> (f64, f64) {
+    let (a_k, b_k) = k_factor;
+    let (player_win_percentage, _) = get_win_percentage(player_rating, opponent_player_rating);
+    let score = match r.win_count.cmp(&r.lose_count) {
+        cmp::Ordering::Greater => 1_f64,
+        cmp::Ordering::Less => 0_f64,
+        cmp::Ordering::Equal => 0.5,
+    };
+    let margin = r.win_count.abs_diff(r.lose_count);
+    let multiplier = if margin == 0 {
+        1_f64
+    } else {
+        let winner_rating_diff = if r.win_count > r.lose_count {
+            player_rating - opponent_player_rating
+        } else {
+            opponent_player_rating - player_rating
+        };
+        (margin as f64 + 1_f64).ln() / 2_f64.ln() * 2.2 / (winner_rating_diff * 0.001 + 2.2)
+    };
+    let delta = (score - player_win_percentage) * multiplier;
+    (
+        player_rating + delta * a_k,
+        opponent_player_rating - delta * b_k,
+    )
+}
+
+// k_factor は (a の K, b の K)。K が違うと a の増減と b の増減は釣り合わない
+pub fn update_rating(
+    a_rate: &f64,
+    b_rate: &f64,
+    a_win: &bool,
+    k_factor: &(f64, f64),
+) -> (f64, f64) {
+    let (a_k, b_k) = k_factor;
     let a_win_percentage = 1_f64 / (10_f64.powf((b_rate - a_rate) / 400_f64) + 1_f64);
     if *a_win {
         let b_win_percentage = 1_f64 - a_win_percentage;
-        let a_win_increment = b_win_percentage * K;
-        (a_rate + a_win_increment, b_rate - a_win_increment)
+        (
+            a_rate + b_win_percentage * a_k,
+            b_rate - b_win_percentage * b_k,
+        )
     } else {
-        let b_win_increment = a_win_percentage * K;
-        (a_rate - b_win_increment, b_rate + b_win_increment)
+        (
+            a_rate - a_win_percentage * a_k,
+            b_rate + a_win_percentage * b_k,
+        )
     }
 }
 
@@ -170,3 +1338,798 @@ pub fn get_win_percentage(a_rate: f64, b_rate: f64) -> (f64, f64) {
     let a_win_percentage = 1_f64 / (10_f64.powf((b_rate - a_rate) / 400_f64) + 1_f64);
     (a_win_percentage, 1_f64 - a_win_percentage)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create_setting() -> EloSetting {
+        EloSetting::new(
+            (1..=4)
+                .map(|id| Player::new(id, &format!("p{}", id)))
+                .collect(),
+            vec![
+                Tournament {
+                    id: 1,
+                    name: "weekly".to_string(),
+                    tier: TournamentTier::Weekly,
+                },
+                Tournament {
+                    id: 2,
+                    name: "major".to_string(),
+                    tier: TournamentTier::Major,
+                },
+            ],
+            vec![
+                TournamentSub {
+                    id: 1,
+                    name: "pools".to_string(),
+                },
+                TournamentSub {
+                    id: 2,
+                    name: "top8".to_string(),
+                },
+            ],
+            (1..=3)
+                .map(|id| Character {
+                    id,
+                    name: format!("c{}", id),
+                })
+                .collect(),
+        )
+    }
+
+    // 大会 1、サブ大会 1、キャラクター 1 同士のレコード
+    fn create_record(
+        date: u64,
+        player_id: u32,
+        opponent_player_id: u32,
+        win: u32,
+        lose: u32,
+    ) -> EloRecord {
+        EloRecord::new(1, 1, date, player_id, 1, opponent_player_id, 1, win, lose)
+    }
+
+    fn get(rating_map: &HashMap<EloRatingKey, f64>, key: EloRatingKey) -> f64 {
+        rating_map[&key]
+    }
+
+    fn assert_close(a: f64, b: f64) {
+        assert!((a - b).abs() < 1e-9, "{} != {}", a, b);
+    }
+
+    fn series_setting() -> EloRatingSetting {
+        EloRatingSetting {
+            series_mode: EloSeriesMode::Series,
+            ..EloRatingSetting::default()
+        }
+    }
+
+    #[test]
+    fn rejects_record_without_games() {
+        let error = get_rating(
+            &create_setting(),
+            &[create_record(1, 1, 2, 0, 0)],
+            &series_setting(),
+        )
+        .unwrap_err();
+        assert_eq!(error, "レコード0: 勝ち数と負け数がどちらも 0");
+    }
+
+    #[test]
+    fn series_counts_once_and_scales_by_margin() {
+        let elo_setting = create_setting();
+        let rating_setting = series_setting();
+        let one_game = get_rating(
+            &elo_setting,
+            &[create_record(1, 1, 2, 1, 0)],
+            &rating_setting,
+        )
+        .unwrap();
+        // 同じレートなら勝率 0.5 なので K / 2
+        assert_close(get(&one_game, EloRatingKey::Player(1)), 1508_f64);
+        assert_close(get(&one_game, EloRatingKey::Player(2)), 1492_f64);
+        let sweep = get_rating(
+            &elo_setting,
+            &[create_record(1, 1, 2, 3, 0)],
+            &rating_setting,
+        )
+        .unwrap();
+        // 3本差は ln(4) / ln(2) = 2 倍
+        assert_close(get(&sweep, EloRatingKey::Player(1)), 1516_f64);
+        let draw = get_rating(
+            &elo_setting,
+            &[create_record(1, 1, 2, 1, 1)],
+            &rating_setting,
+        )
+        .unwrap();
+        assert_close(get(&draw, EloRatingKey::Player(1)), 1500_f64);
+    }
+
+    #[test]
+    fn game_order_depends_on_order_only_when_recorded() {
+        let elo_setting = create_setting();
+        let rating_setting = EloRatingSetting {
+            series_mode: EloSeriesMode::GameOrder,
+            ..EloRatingSetting::default()
+        };
+        let get_player_1 = |record: EloRecord| {
+            get(
+                &get_rating(&elo_setting, &[record], &rating_setting).unwrap(),
+                EloRatingKey::Player(1),
+            )
+        };
+        let win_then_lose =
+            get_player_1(create_record(1, 1, 2, 1, 1).with_game_results(vec![true, false]));
+        let lose_then_win =
+            get_player_1(create_record(1, 1, 2, 1, 1).with_game_results(vec![false, true]));
+        assert!(lose_then_win > win_then_lose);
+        // 順がなければ GameByGame と同じ
+        let by_game = get(
+            &get_rating(
+                &elo_setting,
+                &[create_record(1, 1, 2, 1, 1)],
+                &EloRatingSetting::default(),
+            )
+            .unwrap(),
+            EloRatingKey::Player(1),
+        );
+        assert_close(get_player_1(create_record(1, 1, 2, 1, 1)), by_game);
+        assert_close(win_then_lose, by_game);
+    }
+
+    #[test]
+    fn rejects_game_results_not_matching_counts() {
+        let record = create_record(1, 1, 2, 2, 0).with_game_results(vec![true, false]);
+        assert!(validate_record(&create_setting(), &record).is_err());
+    }
+
+    fn mode_setting(rating_mode: EloRatingMode) -> EloRatingSetting {
+        EloRatingSetting {
+            rating_mode,
+            ..EloRatingSetting::default()
+        }
+    }
+
+    #[test]
+    fn history_matches_final_player_rating() {
+        let elo_setting = create_setting();
+        let record_list = vec![
+            create_record(1, 1, 2, 2, 1),
+            create_record(2, 2, 3, 2, 0),
+            create_record(3, 1, 3, 0, 2),
+        ];
+        let rating_setting = EloRatingSetting::default();
+        let history =
+            get_player_rating_history(&elo_setting, &record_list, &rating_setting).unwrap();
+        assert_eq!(history.len(), 6);
+        let rating_map = get_rating(&elo_setting, &record_list, &rating_setting).unwrap();
+        for player_id in 1..=3 {
+            let timeline = get_player_timeline(&history, player_id);
+            assert_eq!(timeline.len(), 2);
+            assert_close(timeline[0].rating_before, 1500_f64);
+            assert_close(timeline[1].rating_before, timeline[0].rating_after);
+            assert_close(
+                timeline[1].rating_after,
+                get(&rating_map, EloRatingKey::Player(player_id)),
+            );
+        }
+    }
+
+    #[test]
+    fn history_in_character_mode_has_no_player_timeline() {
+        let elo_setting = create_setting();
+        let record_list = vec![EloRecord::new(1, 1, 1, 1, 1, 2, 2, 1, 0)];
+        let history = get_player_rating_history(
+            &elo_setting,
+            &record_list,
+            &mode_setting(EloRatingMode::Character),
+        )
+        .unwrap();
+        let keys: Vec<(EloRatingKey, u32)> = history.iter().map(|c| (c.key, c.player_id)).collect();
+        assert_eq!(
+            keys,
+            vec![
+                (EloRatingKey::Character(1), 1),
+                (EloRatingKey::Character(2), 2)
+            ]
+        );
+        assert_close(history[0].rating_after, 1508_f64);
+        assert!(get_player_timeline(&history, 1).is_empty());
+        assert!(get_tournament_rating_changes(&history, 1).is_empty());
+    }
+
+    #[test]
+    fn history_in_player_and_character_mode_keeps_each_key() {
+        let elo_setting = create_setting();
+        let record_list = vec![
+            EloRecord::new(1, 1, 1, 1, 1, 2, 2, 1, 0),
+            EloRecord::new(1, 1, 2, 1, 3, 2, 2, 1, 0),
+        ];
+        let rating_setting = mode_setting(EloRatingMode::PlayerAndCharacter);
+        let history =
+            get_player_rating_history(&elo_setting, &record_list, &rating_setting).unwrap();
+        assert_eq!(history.len(), 8);
+        let rating_map = get_rating(&elo_setting, &record_list, &rating_setting).unwrap();
+        for change in history.iter().skip(4) {
+            assert_close(change.rating_after, get(&rating_map, change.key));
+        }
+        // キャラクター 3 は2試合目から始まる
+        let character_3: Vec<&EloRatingChange> = history
+            .iter()
+            .filter(|c| c.key == EloRatingKey::PlayerCharacter(1, 3))
+            .collect();
+        assert_eq!(character_3.len(), 1);
+        assert_close(character_3[0].rating_before, 1500_f64);
+        let timeline = get_player_timeline(&history, 1);
+        assert_eq!(timeline.len(), 2);
+        assert_close(timeline[1].rating_before, timeline[0].rating_after);
+        assert_eq!(
+            get_tournament_rating_changes(&history, 1),
+            vec![
+                (1, 1500_f64, get(&rating_map, EloRatingKey::Player(1))),
+                (2, 1500_f64, get(&rating_map, EloRatingKey::Player(2))),
+            ]
+        );
+    }
+
+    fn decay_setting() -> EloRatingSetting {
+        EloRatingSetting {
+            inactivity_policy: Some(InactivityPolicy {
+                inactive_after: 10,
+                decay_period: 5,
+                decay_rate: 0.5,
+            }),
+            initial_ratings: HashMap::from([(3, 1700_f64)]),
+            ..EloRatingSetting::default()
+        }
+    }
+
+    #[test]
+    fn decayed_rating_map_pulls_idle_ratings_to_initial_rating() {
+        let elo_setting = create_setting();
+        let rating_setting = decay_setting();
+        let mut state = EloRatingState::new(&elo_setting, &rating_setting);
+        apply_records(
+            &mut state,
+            &elo_setting,
+            &[create_record(1, 1, 2, 1, 0), create_record(1, 3, 4, 1, 0)],
+            &rating_setting,
+        )
+        .unwrap();
+        let player_1 = get(&state.rating_map, EloRatingKey::Player(1));
+        let player_3 = get(&state.rating_map, EloRatingKey::Player(3));
+        // 空いていなければそのまま
+        let rating_map = get_decayed_rating_map(&state, &rating_setting, 11);
+        assert_close(get(&rating_map, EloRatingKey::Player(1)), player_1);
+        // 10 を超えてから 5 ごとに半分。初期レートを決めたプレイヤー 3 も 1500 に寄せる
+        let rating_map = get_decayed_rating_map(&state, &rating_setting, 21);
+        assert_close(
+            get(&rating_map, EloRatingKey::Player(1)),
+            1500_f64 + (player_1 - 1500_f64) / 4_f64,
+        );
+        assert_close(
+            get(&rating_map, EloRatingKey::Player(3)),
+            1500_f64 + (player_3 - 1500_f64) / 4_f64,
+        );
+        // 状態には縮める前のレートが残る
+        assert_close(get(&state.rating_map, EloRatingKey::Player(1)), player_1);
+        // 次に対戦するときは読み出したときと同じだけ縮めてから反映する
+        let history = get_player_rating_history(
+            &elo_setting,
+            &[create_record(1, 1, 2, 1, 0), create_record(21, 1, 4, 1, 0)],
+            &rating_setting,
+        )
+        .unwrap();
+        assert_close(
+            history[2].rating_before,
+            get(&rating_map, EloRatingKey::Player(1)),
+        );
+    }
+
+    #[test]
+    fn leaderboard_uses_decayed_ratings_and_drops_inactive_players() {
+        let elo_setting = create_setting();
+        let rating_setting = decay_setting();
+        let mut state = EloRatingState::new(&elo_setting, &rating_setting);
+        apply_records(
+            &mut state,
+            &elo_setting,
+            &[create_record(1, 1, 2, 3, 0), create_record(15, 3, 4, 0, 1)],
+            &rating_setting,
+        )
+        .unwrap();
+        let leaderboard = get_leaderboard(&state, &rating_setting, 15);
+        let player_ids: Vec<u32> = leaderboard
+            .iter()
+            .map(|(player_id, _)| *player_id)
+            .collect();
+        assert_eq!(player_ids, vec![3, 4]);
+        let rating_map = get_decayed_rating_map(&state, &rating_setting, 15);
+        assert_close(leaderboard[0].1, get(&rating_map, EloRatingKey::Player(3)));
+        // inactivity_policy がなければ、対戦したプレイヤー全員を縮めずに並べる
+        let leaderboard = get_leaderboard(&state, &EloRatingSetting::default(), 15);
+        assert_eq!(leaderboard.len(), 4);
+        assert_close(
+            leaderboard[0].1,
+            get(&state.rating_map, EloRatingKey::Player(3)),
+        );
+    }
+
+    #[test]
+    fn saved_state_continues_like_full_replay() {
+        let elo_setting = create_setting();
+        let rating_setting = EloRatingSetting {
+            rating_mode: EloRatingMode::PlayerAndCharacter,
+            k_factor: KFactor::GamesPlayed {
+                provisional_k: 32_f64,
+                k: 16_f64,
+                provisional_game_count: 3,
+            },
+            ..EloRatingSetting::default()
+        };
+        let first = vec![
+            EloRecord::new(1, 1, 1, 1, 1, 2, 2, 2, 1),
+            EloRecord::new(1, 2, 1, 3, 1, 4, 3, 0, 2),
+        ];
+        let second = vec![
+            EloRecord::new(2, 1, 1, 1, 2, 3, 1, 2, 0),
+            EloRecord::new(1, 1, 5, 2, 2, 4, 3, 1, 2),
+        ];
+        let mut state = EloRatingState::new(&elo_setting, &rating_setting);
+        apply_records(&mut state, &elo_setting, &first, &rating_setting).unwrap();
+        let path = std::env::temp_dir().join(format!("elo_state_test_{}.json", std::process::id()));
+        let path = path.to_str().unwrap();
+        save_rating_state(path, &state).unwrap();
+        let mut loaded_state = load_rating_state(path).unwrap();
+        fs::remove_file(path).unwrap();
+        assert_eq!(loaded_state.last_order, Some((1, 1, 2)));
+        apply_records(&mut loaded_state, &elo_setting, &second, &rating_setting).unwrap();
+
+        let all: Vec<EloRecord> = first.into_iter().chain(second).collect();
+        let rating_map = get_rating(&elo_setting, &all, &rating_setting).unwrap();
+        assert_eq!(loaded_state.rating_map.len(), rating_map.len());
+        for (key, rating) in rating_map.iter() {
+            assert_close(loaded_state.rating_map[key], *rating);
+        }
+        assert_eq!(loaded_state.last_order, Some((5, 1, 1)));
+    }
+
+    #[test]
+    fn apply_records_rejects_records_not_after_last_order() {
+        let elo_setting = create_setting();
+        let rating_setting = EloRatingSetting::default();
+        let mut state = EloRatingState::new(&elo_setting, &rating_setting);
+        let record = EloRecord::new(1, 2, 3, 1, 1, 2, 1, 2, 0);
+        apply_records(
+            &mut state,
+            &elo_setting,
+            std::slice::from_ref(&record),
+            &rating_setting,
+        )
+        .unwrap();
+        let rating_map = state.rating_map.clone();
+        // 同じレコードをもう一度
+        assert!(apply_records(&mut state, &elo_setting, &[record], &rating_setting).is_err());
+        // 同じ日付の前のサブ大会
+        assert!(apply_records(
+            &mut state,
+            &elo_setting,
+            &[EloRecord::new(1, 1, 3, 1, 1, 2, 1, 2, 0)],
+            &rating_setting
+        )
+        .is_err());
+        assert_eq!(state.rating_map, rating_map);
+        // 同じ日付の後の大会は反映できる
+        apply_records(
+            &mut state,
+            &elo_setting,
+            &[EloRecord::new(2, 1, 3, 1, 1, 2, 1, 2, 0)],
+            &rating_setting,
+        )
+        .unwrap();
+        assert_eq!(state.last_order, Some((3, 2, 1)));
+    }
+
+    #[test]
+    fn find_player_by_name_ignores_case_and_reads_aliases() {
+        let mut elo_setting = create_setting();
+        elo_setting.add_player_alias(2, "  Old Tag ").unwrap();
+        assert_eq!(
+            elo_setting.find_player_by_name("P1").map(|p| p.id).unwrap(),
+            1
+        );
+        assert_eq!(
+            elo_setting
+                .find_player_by_name("old tag")
+                .map(|p| p.id)
+                .unwrap(),
+            2
+        );
+        assert_eq!(
+            elo_setting
+                .find_player_by_name("nobody")
+                .map(|p| p.id)
+                .unwrap_err(),
+            "不明な選手: nobody"
+        );
+        elo_setting.add_player_alias(3, "OLD TAG").unwrap();
+        assert_eq!(
+            elo_setting
+                .find_player_by_name("old tag")
+                .map(|p| p.id)
+                .unwrap_err(),
+            "old tag に一致する選手が複数いる: 2, 3"
+        );
+        // 同じ名前は別名に足さない
+        elo_setting.add_player_alias(2, "old tag").unwrap();
+        assert_eq!(elo_setting.find_player(2).unwrap().aliases, vec!["Old Tag"]);
+    }
+
+    #[test]
+    fn merge_players_moves_names_and_records() {
+        let mut elo_setting = create_setting();
+        elo_setting.add_player_alias(2, "p2 alt").unwrap();
+        let mut record_list = vec![
+            create_record(1, 1, 2, 2, 0),
+            create_record(2, 2, 3, 2, 1),
+            create_record(3, 4, 2, 0, 2),
+        ];
+        let rating_setting = EloRatingSetting::default();
+        let state =
+            merge_players(&mut elo_setting, &mut record_list, 2, 1, &rating_setting).unwrap();
+        assert!(elo_setting.find_player(2).is_none());
+        assert_eq!(
+            elo_setting.find_player(1).unwrap().aliases,
+            vec!["p2", "p2 alt"]
+        );
+        assert_eq!(
+            elo_setting
+                .find_player_by_name("P2 ALT")
+                .map(|p| p.id)
+                .unwrap(),
+            1
+        );
+        // 1 と 2 の対戦は取り除き、残りは 1 のレコードにする
+        let players: Vec<(u32, u32)> = record_list
+            .iter()
+            .map(|r| (r.player_id, r.opponent_player_id))
+            .collect();
+        assert_eq!(players, vec![(1, 3), (4, 1)]);
+        assert_eq!(
+            state.rating_map,
+            get_rating(&elo_setting, &record_list, &rating_setting).unwrap()
+        );
+        assert!(!state.rating_map.contains_key(&EloRatingKey::Player(2)));
+    }
+
+    #[test]
+    fn merge_players_changes_nothing_on_error() {
+        let mut elo_setting = create_setting();
+        // 未登録の大会のレコードがあるので計算し直せない
+        let mut record_list = vec![
+            create_record(1, 2, 3, 2, 0),
+            EloRecord::new(9, 1, 2, 2, 1, 3, 1, 2, 0),
+        ];
+        let rating_setting = EloRatingSetting::default();
+        let error = match merge_players(&mut elo_setting, &mut record_list, 2, 1, &rating_setting) {
+            Ok(_) => panic!("エラーにならない"),
+            Err(e) => e,
+        };
+        assert_eq!(error, "レコード1: 未登録の大会: 9");
+        assert_eq!(elo_setting.get_player_ids(), vec![1, 2, 3, 4]);
+        assert!(elo_setting.find_player(1).unwrap().aliases.is_empty());
+        let players: Vec<(u32, u32)> = record_list
+            .iter()
+            .map(|r| (r.player_id, r.opponent_player_id))
+            .collect();
+        assert_eq!(players, vec![(2, 3), (2, 3)]);
+        for (from, into) in [(1, 1), (9, 1), (1, 9)] {
+            assert!(merge_players(
+                &mut elo_setting,
+                &mut record_list,
+                from,
+                into,
+                &rating_setting
+            )
+            .is_err());
+        }
+        assert_eq!(elo_setting.get_player_ids(), vec![1, 2, 3, 4]);
+        assert_eq!(record_list.len(), 2);
+    }
+
+    #[test]
+    fn records_are_sorted_by_date_tournament_and_sub() {
+        let elo_setting = create_setting();
+        let record_list = vec![
+            EloRecord::new(1, 1, 3, 1, 1, 2, 1, 1, 0),
+            EloRecord::new(2, 1, 2, 3, 1, 4, 1, 1, 0),
+            EloRecord::new(1, 2, 2, 1, 1, 3, 1, 1, 0),
+            EloRecord::new(1, 1, 2, 2, 1, 4, 1, 1, 0),
+            EloRecord::new(1, 1, 2, 4, 1, 1, 1, 1, 0),
+        ];
+        let sorted: Vec<(u32, u32)> = sort_and_validate_records(&elo_setting, &record_list)
+            .unwrap()
+            .iter()
+            .map(|r| (r.player_id, r.opponent_player_id))
+            .collect();
+        // 同じ (日付, 大会, サブ大会) は元の順
+        assert_eq!(sorted, vec![(2, 4), (4, 1), (1, 3), (3, 4), (1, 2)]);
+        // 並べ替えてから反映するので、元の並びによらない
+        let rating_setting = EloRatingSetting::default();
+        let mut sorted_record_list: Vec<EloRecord> = vec![
+            record_list[3].clone(),
+            record_list[4].clone(),
+            record_list[2].clone(),
+            record_list[1].clone(),
+            record_list[0].clone(),
+        ];
+        let rating_map = get_rating(&elo_setting, &record_list, &rating_setting).unwrap();
+        assert_eq!(
+            rating_map,
+            get_rating(&elo_setting, &sorted_record_list, &rating_setting).unwrap()
+        );
+        // 同じ並び順の2件を入れ替えると結果が変わる（元の順を保っている）
+        sorted_record_list.swap(0, 1);
+        assert_ne!(
+            rating_map,
+            get_rating(&elo_setting, &sorted_record_list, &rating_setting).unwrap()
+        );
+    }
+
+    #[test]
+    fn reports_every_invalid_record_with_its_index() {
+        let elo_setting = create_setting();
+        let record_list = vec![
+            create_record(1, 1, 2, 1, 0),
+            EloRecord::new(7, 1, 1, 1, 1, 2, 1, 1, 0),
+            EloRecord::new(1, 7, 1, 1, 1, 2, 1, 1, 0),
+            create_record(1, 1, 9, 1, 0),
+            EloRecord::new(1, 1, 1, 1, 8, 2, 1, 1, 0),
+            create_record(1, 3, 3, 1, 0),
+        ];
+        assert_eq!(
+            sort_and_validate_records(&elo_setting, &record_list)
+                .map(|_| ())
+                .unwrap_err(),
+            [
+                "レコード1: 未登録の大会: 7",
+                "レコード2: 未登録のサブ大会: 7",
+                "レコード3: 未登録のプレイヤー: 9",
+                "レコード4: 未登録のキャラクター: 8",
+                "レコード5: 同じプレイヤー同士の対戦: 3",
+            ]
+            .join("\n")
+        );
+    }
+
+    #[test]
+    fn player_character_mode_rates_each_character_separately() {
+        let elo_setting = create_setting();
+        let record_list = vec![
+            EloRecord::new(1, 1, 1, 1, 1, 2, 1, 1, 0),
+            EloRecord::new(1, 1, 2, 1, 2, 2, 2, 0, 1),
+        ];
+        let rating_map = get_rating(
+            &elo_setting,
+            &record_list,
+            &mode_setting(EloRatingMode::PlayerCharacter),
+        )
+        .unwrap();
+        assert_close(
+            get(&rating_map, EloRatingKey::PlayerCharacter(1, 1)),
+            1508_f64,
+        );
+        assert_close(
+            get(&rating_map, EloRatingKey::PlayerCharacter(1, 2)),
+            1492_f64,
+        );
+        // プレイヤーのキーは初期レートのまま
+        assert_close(get(&rating_map, EloRatingKey::Player(1)), 1500_f64);
+        let ratings = get_player_character_ratings(&rating_map, 1);
+        assert_eq!(
+            ratings.iter().map(|(id, _)| *id).collect::<Vec<u32>>(),
+            vec![1, 2]
+        );
+    }
+
+    #[test]
+    fn character_mode_skips_mirror_matches() {
+        let elo_setting = create_setting();
+        let record_list = vec![
+            EloRecord::new(1, 1, 1, 1, 1, 2, 1, 2, 0),
+            EloRecord::new(1, 1, 2, 1, 3, 2, 2, 1, 0),
+        ];
+        let rating_setting = mode_setting(EloRatingMode::Character);
+        let rating_map = get_rating(&elo_setting, &record_list, &rating_setting).unwrap();
+        assert!(!rating_map.contains_key(&EloRatingKey::Character(1)));
+        assert_close(get(&rating_map, EloRatingKey::Character(3)), 1508_f64);
+        assert_eq!(
+            get_character_tier_list(&rating_map),
+            vec![(3, 1508_f64), (2, 1492_f64)]
+        );
+        assert_eq!(
+            get_player_rating_history(&elo_setting, &record_list, &rating_setting)
+                .unwrap()
+                .len(),
+            2
+        );
+    }
+
+    #[test]
+    fn player_and_character_mode_moves_both_keys_by_the_mean() {
+        let elo_setting = create_setting();
+        let rating_setting = EloRatingSetting {
+            rating_mode: EloRatingMode::PlayerAndCharacter,
+            initial_ratings: HashMap::from([(1, 1700_f64)]),
+            ..EloRatingSetting::default()
+        };
+        let rating_map = get_rating(
+            &elo_setting,
+            &[EloRecord::new(1, 1, 1, 1, 1, 2, 1, 0, 1)],
+            &rating_setting,
+        )
+        .unwrap();
+        // 1 の平均は 1700、2 は 1500
+        let (win_percentage, _) = get_win_percentage(1700_f64, 1500_f64);
+        let delta = win_percentage * DEFAULT_K;
+        assert_close(get(&rating_map, EloRatingKey::Player(1)), 1700_f64 - delta);
+        assert_close(
+            get(&rating_map, EloRatingKey::PlayerCharacter(1, 1)),
+            1700_f64 - delta,
+        );
+        assert_close(get(&rating_map, EloRatingKey::Player(2)), 1500_f64 + delta);
+        assert_close(
+            get(&rating_map, EloRatingKey::PlayerCharacter(2, 1)),
+            1500_f64 + delta,
+        );
+    }
+
+    #[test]
+    fn k_factor_policies() {
+        assert_eq!(KFactor::Fixed(20_f64).get_k(2000_f64, 100), 20_f64);
+        let games_played = KFactor::GamesPlayed {
+            provisional_k: 40_f64,
+            k: 20_f64,
+            provisional_game_count: 10,
+        };
+        assert_eq!(games_played.get_k(1500_f64, 9), 40_f64);
+        assert_eq!(games_played.get_k(1500_f64, 10), 20_f64);
+        let rating_band = KFactor::RatingBand(vec![
+            (1400_f64, 32_f64),
+            (2000_f64, 24_f64),
+            (2400_f64, 16_f64),
+        ]);
+        assert_eq!(rating_band.get_k(1000_f64, 0), 32_f64);
+        assert_eq!(rating_band.get_k(1999_f64, 0), 32_f64);
+        assert_eq!(rating_band.get_k(2000_f64, 0), 24_f64);
+        assert_eq!(rating_band.get_k(2500_f64, 0), 16_f64);
+        assert_eq!(KFactor::RatingBand(vec![]).get_k(1500_f64, 0), DEFAULT_K);
+    }
+
+    #[test]
+    fn k_factor_per_player_and_tier() {
+        let elo_setting = create_setting();
+        let rating_setting = EloRatingSetting {
+            k_factor: KFactor::GamesPlayed {
+                provisional_k: 32_f64,
+                k: 16_f64,
+                provisional_game_count: 2,
+            },
+            tier_multipliers: HashMap::from([(TournamentTier::Major, 2_f64)]),
+            ..EloRatingSetting::default()
+        };
+        // 1 は2本戦ったあとなので K 16、3 は初めてなので K 32。大会 2 は Major で2倍
+        let rating_map = get_rating(
+            &elo_setting,
+            &[
+                create_record(1, 1, 2, 1, 1),
+                EloRecord::new(2, 1, 2, 1, 1, 3, 1, 1, 0),
+            ],
+            &rating_setting,
+        )
+        .unwrap();
+        let player_1_before = {
+            let rating_map = get_rating(
+                &elo_setting,
+                &[create_record(1, 1, 2, 1, 1)],
+                &rating_setting,
+            )
+            .unwrap();
+            get(&rating_map, EloRatingKey::Player(1))
+        };
+        let (_, lose_percentage) = get_win_percentage(player_1_before, 1500_f64);
+        assert_close(
+            get(&rating_map, EloRatingKey::Player(1)),
+            player_1_before + lose_percentage * 32_f64,
+        );
+        assert_close(
+            get(&rating_map, EloRatingKey::Player(3)),
+            1500_f64 - lose_percentage * 64_f64,
+        );
+    }
+
+    #[test]
+    fn keys_start_from_initial_ratings() {
+        let rating_setting = EloRatingSetting {
+            initial_rating: 1400_f64,
+            initial_ratings: HashMap::from([(1, 2200_f64)]),
+            ..EloRatingSetting::default()
+        };
+        let state = EloRatingState::new(&create_setting(), &rating_setting);
+        assert_eq!(get(&state.rating_map, EloRatingKey::Player(1)), 2200_f64);
+        assert_eq!(get(&state.rating_map, EloRatingKey::Player(2)), 1400_f64);
+        assert_eq!(
+            rating_setting.get_key_initial_rating(&EloRatingKey::PlayerCharacter(1, 3)),
+            2200_f64
+        );
+        assert_eq!(
+            rating_setting.get_key_initial_rating(&EloRatingKey::PlayerCharacter(2, 3)),
+            1400_f64
+        );
+        assert_eq!(
+            rating_setting.get_key_initial_rating(&EloRatingKey::Character(1)),
+            1400_f64
+        );
+        let history = get_player_rating_history(
+            &create_setting(),
+            &[EloRecord::new(1, 1, 1, 1, 3, 2, 3, 2, 0)],
+            &EloRatingSetting {
+                rating_mode: EloRatingMode::PlayerCharacter,
+                ..rating_setting.clone()
+            },
+        )
+        .unwrap();
+        assert_eq!(history[0].rating_before, 2200_f64);
+        assert_eq!(history[1].rating_before, 1400_f64);
+    }
+
+    #[test]
+    fn players_added_later_start_from_their_initial_rating() {
+        let rating_setting = EloRatingSetting {
+            initial_ratings: HashMap::from([(5, 1800_f64)]),
+            ..EloRatingSetting::default()
+        };
+        let elo_setting = create_setting();
+        let mut state = EloRatingState::new(&elo_setting, &rating_setting);
+        apply_records(
+            &mut state,
+            &elo_setting,
+            &[create_record(1, 1, 2, 1, 0)],
+            &rating_setting,
+        )
+        .unwrap();
+        let mut player_list = elo_setting.get_player_list().to_vec();
+        player_list.push(Player::new(5, "p5"));
+        let elo_setting = EloSetting::new(
+            player_list,
+            elo_setting.get_tournament_list().to_vec(),
+            elo_setting.get_tournament_sub_list().to_vec(),
+            elo_setting.get_character_list().to_vec(),
+        );
+        apply_records(
+            &mut state,
+            &elo_setting,
+            &[create_record(2, 5, 3, 1, 0)],
+            &rating_setting,
+        )
+        .unwrap();
+        let (_, lose_percentage) = get_win_percentage(1800_f64, 1500_f64);
+        assert_close(
+            get(&state.rating_map, EloRatingKey::Player(5)),
+            1800_f64 + lose_percentage * DEFAULT_K,
+        );
+    }
+
+    #[test]
+    fn seed_by_placement_spreads_by_log_placement() {
+        let ratings = seed_by_placement(&[(1, 1), (2, 2), (3, 4), (4, 4)], 2000_f64, 1600_f64);
+        assert_close(ratings[&1], 2000_f64);
+        assert_close(ratings[&2], 1800_f64);
+        assert_close(ratings[&3], 1600_f64);
+        assert_close(ratings[&4], 1600_f64);
+        // 全員同じ順位なら best_rating
+        let ratings = seed_by_placement(&[(1, 1), (2, 1)], 2000_f64, 1600_f64);
+        assert_close(ratings[&2], 2000_f64);
+    }
+}
@@ -0,0 +1,249 @@
+use crate::elo::{sort_and_validate_records, EloRecord, EloSetting};
+use std::collections::HashMap;
+
+// Glicko-2 (http://www.glicko.net/glicko/glicko2.pdf)
+// EloRecord の勝ち数、負け数を1本ずつの対戦として扱い、date を period_length ごとに区切った期間でまとめて更新する
+
+// Glicko スケールと Glicko-2 スケールの比
+const SCALE: f64 = 173.7178;
+// ボラティリティの反復計算の収束判定
+const CONVERGENCE_TOLERANCE: f64 = 0.000001;
+
+#[derive(Clone, Debug)]
+pub struct Glicko2Setting {
+    // 1期間の長さ（EloRecord::date と同じ単位）。デフォルトは date を UNIX 時間の秒とみて1週間
+    pub period_length: u64,
+    // ボラティリティの変化のしやすさ（0.3〜1.2 程度）
+    pub tau: f64,
+    pub initial_rating: f64,
+    // 初期値かつ上限
+    pub initial_deviation: f64,
+    pub initial_volatility: f64,
+}
+
+impl Default for Glicko2Setting {
+    fn default() -> Self {
+        Glicko2Setting {
+            period_length: 7 * 24 * 60 * 60,
+            tau: 0.5,
+            initial_rating: 1500_f64,
+            initial_deviation: 350_f64,
+            initial_volatility: 0.06,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Glicko2Rating {
+    pub rating: f64,
+    pub deviation: f64,
+    pub volatility: f64,
+}
+
+// 期間の途中の計算は Glicko-2 スケールで持つ
+#[derive(Clone, Copy)]
+struct Glicko2State {
+    mu: f64,
+    phi: f64,
+    sigma: f64,
+    // 最後に反映した期間。None ならまだ一度も対戦していない
+    period: Option<u64>,
+}
+
+pub fn get_player_glicko2_rating(
+    elo_setting: &EloSetting,
+    record_list: &[EloRecord],
+    setting: &Glicko2Setting,
+) -> Result<HashMap<u32, Glicko2Rating>, String> {
+    if setting.period_length == 0 {
+        return Err("period_length は 1 以上にしてください".to_string());
+    }
+    let sorted_record_list = sort_and_validate_records(elo_setting, record_list)?;
+    let max_phi = setting.initial_deviation / SCALE;
+    let mut state_map: HashMap<u32, Glicko2State> = HashMap::new();
+    for player_id in elo_setting.get_player_ids() {
+        state_map.insert(
+            player_id,
+            Glicko2State {
+                mu: 0_f64,
+                phi: max_phi,
+                sigma: setting.initial_volatility,
+                period: None,
+            },
+        );
+    }
+
+    let mut last_period: Option<u64> = None;
+    let mut start = 0;
+    while start < sorted_record_list.len() {
+        let period = sorted_record_list[start].date / setting.period_length;
+        let end = sorted_record_list[start..]
+            .iter()
+            .position(|r| r.date / setting.period_length != period)
+            .map_or(sorted_record_list.len(), |n| start + n);
+
+        // プレイヤーごとに、期間の始めのレートでの (相手の mu, 相手の phi, 結果) を集める
+        let mut game_map: HashMap<u32, Vec<(f64, f64, f64)>> = HashMap::new();
+        for r in sorted_record_list[start..end].iter() {
+            let player = state_map[&r.player_id];
+            let opponent_player = state_map[&r.opponent_player_id];
+            for (score, count) in [(1_f64, r.win_count), (0_f64, r.lose_count)] {
+                for _ in 0..count {
+                    game_map.entry(r.player_id).or_default().push((
+                        opponent_player.mu,
+                        opponent_player.phi,
+                        score,
+                    ));
+                    game_map.entry(r.opponent_player_id).or_default().push((
+                        player.mu,
+                        player.phi,
+                        1_f64 - score,
+                    ));
+                }
+            }
+        }
+        for (player_id, games) in game_map.iter() {
+            let state = state_map.get_mut(player_id).unwrap();
+            // 対戦のなかった期間の分だけ phi を広げてから更新する
+            let idle_period_count = state.period.map_or(0, |p| period - p - 1);
+            state.phi = grow_phi(state.phi, state.sigma, idle_period_count, max_phi);
+            *state = update_state(state, games, setting.tau);
+            state.period = Some(period);
+        }
+        last_period = Some(period);
+        start = end;
+    }
+
+    Ok(state_map
+        .into_iter()
+        .map(|(player_id, state)| {
+            // 最後の期間まで対戦のなかった期間の分を広げる
+            let idle_period_count = match (state.period, last_period) {
+                (Some(p), Some(last_period)) => last_period - p,
+                _ => 0,
+            };
+            let phi = grow_phi(state.phi, state.sigma, idle_period_count, max_phi);
+            (
+                player_id,
+                Glicko2Rating {
+                    rating: state.mu * SCALE + setting.initial_rating,
+                    deviation: phi * SCALE,
+                    volatility: state.sigma,
+                },
+            )
+        })
+        .collect())
+}
+
+fn grow_phi(phi: f64, sigma: f64, period_count: u64, max_phi: f64) -> f64 {
+    (phi * phi + period_count as f64 * sigma * sigma)
+        .sqrt()
+        .min(max_phi)
+}
+
+fn g(phi: f64) -> f64 {
+    1_f64 / (1_f64 + 3_f64 * phi * phi / (std::f64::consts::PI * std::f64::consts::PI)).sqrt()
+}
+
+fn expected_score(mu: f64, opponent_mu: f64, opponent_phi: f64) -> f64 {
+    1_f64 / (1_f64 + (-g(opponent_phi) * (mu - opponent_mu)).exp())
+}
+
+// 1期間分の対戦 (相手の mu, 相手の phi, 結果) で更新する（論文の Step 3〜8）
+fn update_state(state: &Glicko2State, games: &[(f64, f64, f64)], tau: f64) -> Glicko2State {
+    let mut v_inverse = 0_f64;
+    let mut score_sum = 0_f64;
+    for (opponent_mu, opponent_phi, score) in games.iter() {
+        let g_phi = g(*opponent_phi);
+        let e = expected_score(state.mu, *opponent_mu, *opponent_phi);
+        v_inverse += g_phi * g_phi * e * (1_f64 - e);
+        score_sum += g_phi * (score - e);
+    }
+    let v = 1_f64 / v_inverse;
+    let delta = v * score_sum;
+    let sigma = get_new_volatility(state.phi, state.sigma, v, delta, tau);
+    let phi_star = (state.phi * state.phi + sigma * sigma).sqrt();
+    let phi = 1_f64 / (1_f64 / (phi_star * phi_star) + 1_f64 / v).sqrt();
+    Glicko2State {
+        mu: state.mu + phi * phi * score_sum,
+        phi,
+        sigma,
+        period: state.period,
+    }
+}
+
+// Illinois 法で f(x) = 0 を解く（論文の Step 5）
+fn get_new_volatility(phi: f64, sigma: f64, v: f64, delta: f64, tau: f64) -> f64 {
+    let a = (sigma * sigma).ln();
+    let f = |x: f64| {
+        let ex = x.exp();
+        let d = phi * phi + v + ex;
+        ex * (delta * delta - phi * phi - v - ex) / (2_f64 * d * d) - (x - a) / (tau * tau)
+    };
+    let mut big_a = a;
+    let mut big_b = if delta * delta > phi * phi + v {
+        (delta * delta - phi * phi - v).ln()
+    } else {
+        let mut k = 1_f64;
+        while f(a - k * tau) < 0_f64 {
+            k += 1_f64;
+        }
+        a - k * tau
+    };
+    let mut f_a = f(big_a);
+    let mut f_b = f(big_b);
+    while (big_b - big_a).abs() > CONVERGENCE_TOLERANCE {
+        let big_c = big_a + (big_a - big_b) * f_a / (f_b - f_a);
+        let f_c = f(big_c);
+        if f_c * f_b <= 0_f64 {
+            big_a = big_b;
+            f_a = f_b;
+        } else {
+            f_a /= 2_f64;
+        }
+        big_b = big_c;
+        f_b = f_c;
+    }
+    (big_a / 2_f64).exp()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Glicko-2 の論文の計算例: 1500/200/0.06 のプレイヤーが (1400, 30) に勝ち、(1550, 100), (1700, 300) に負ける
+    fn get_example() -> (Glicko2State, Vec<(f64, f64, f64)>) {
+        let state = Glicko2State {
+            mu: 0_f64,
+            phi: 200_f64 / SCALE,
+            sigma: 0.06,
+            period: None,
+        };
+        let games = [
+            (1400_f64, 30_f64, 1_f64),
+            (1550_f64, 100_f64, 0_f64),
+            (1700_f64, 300_f64, 0_f64),
+        ]
+        .into_iter()
+        .map(|(rating, deviation, score)| ((rating - 1500_f64) / SCALE, deviation / SCALE, score))
+        .collect();
+        (state, games)
+    }
+
+    #[test]
+    fn update_state_matches_paper_example() {
+        let (state, games) = get_example();
+        let new_state = update_state(&state, &games, 0.5);
+        assert!((new_state.mu * SCALE + 1500_f64 - 1464.06).abs() < 0.01);
+        assert!((new_state.phi * SCALE - 151.52).abs() < 0.01);
+        assert!((new_state.sigma - 0.05999).abs() < 0.00001);
+    }
+
+    #[test]
+    fn new_volatility_matches_paper_example() {
+        // 論文の Step 3, 4 の値 v = 1.7785, Δ = -0.4834
+        let (state, _) = get_example();
+        let sigma = get_new_volatility(state.phi, state.sigma, 1.7785, -0.4834, 0.5);
+        assert!((sigma - 0.05999).abs() < 0.00001);
+    }
+}
@@ -0,0 +1,274 @@
+use crate::report::format_percentage;
+use crate::season::SflSeason;
+use crate::sfl::{GameType, SflDivision};
+use crate::simulate::{SimulateSetting, SimulationResult};
+
+// 勝ち抜け予想ページ（sfl.html のレイアウト）をシミュレーション結果から生成する
+
+const STYLE: &str = "<style>
+    table {
+        text-align: center;
+        margin-bottom: 10px;
+    }
+
+    table, tr, th, td {
+        border: 1px solid black;
+        border-collapse: collapse;
+    }
+
+    th, td {
+        padding: 5px;
+    }
+
+    li {
+        list-style: none;
+    }
+</style>
+";
+
+// division_results はディビジョンと get_simulate_result の結果の組
+// form_action を指定すると予想設定と試合結果の入力フォームを付ける（server.rs）
+pub fn render_page(
+    season: &SflSeason,
+    setting: &SimulateSetting,
+    division_results: &[(&SflDivision, Vec<SimulationResult>)],
+    form_action: Option<&str>,
+) -> String {
+    let mut html = String::new();
+    html.push_str("<!DOCTYPE html>\n<html lang=\"ja\">\n<meta charset=\"utf-8\">\n");
+    html.push_str(STYLE);
+    html.push_str("<body>\n");
+    html.push_str(&format!("<h1>{}勝ち抜け予想</h1>\n", escape(&season.name)));
+    html.push_str(&format!(
+        "<p>{}回試行、レーティング設定 {:?}</p>\n",
+        setting.trial_count, setting.sfl_rate_setting
+    ));
+    html.push_str("<h2>リーグ勝ち抜け予想</h2>\n");
+    for (division, results) in division_results.iter() {
+        html.push_str(&render_standings_table(division, results));
+    }
+    html.push_str("<h2>レート</h2>\n");
+    for (division, results) in division_results.iter() {
+        html.push_str(&render_rating_table(division, results));
+    }
+    if let Some(form_action) = form_action {
+        html.push_str(&format!(
+            "<form method=\"post\" action=\"{}\">\n",
+            escape(form_action)
+        ));
+        html.push_str(&render_setting_form(setting));
+        html.push_str("<h2>試合結果を入力</h2>\n");
+        for (division, _) in division_results.iter() {
+            html.push_str(&format!(
+                "<h3>{}</h3>\n",
+                division.sfl_stage.get_display_name()
+            ));
+            for index in 0..division.matches.len() {
+                html.push_str(&render_match_form(division, index));
+            }
+        }
+        html.push_str("<button type=\"submit\">再計算</button>\n</form>\n");
+    }
+    html.push_str("</body>\n</html>\n");
+    html
+}
+
+fn render_standings_table(division: &SflDivision, results: &[SimulationResult]) -> String {
+    // 次のステージ（プレイオフ）に進める順位まで
+    let advance_count = division
+        .sfl_stage
+        .get_next_stage()
+        .map_or(0, |sfl_stage| sfl_stage.get_advance_count());
+    let mut html = format!(
+        "<h3>{}</h3>\n<table>\n    <thead>\n    <tr>\n        <th>チーム</th><th>ポイント<br>現在</th><th>ポイント<br>予想</th><th>得失<br>現在</th><th>得失<br>予想</th><th>プレイオフ<br>進出確率</th>",
+        division.sfl_stage.get_display_name()
+    );
+    for n in 1..=division.teams.len() {
+        html.push_str(&format!("<th>{}位<br>確率</th>", n));
+    }
+    html.push_str("\n    </tr>\n    </thead>\n    <tbody>\n");
+    for result in results.iter() {
+        let advance_probability: f64 = result.place_probabilities.iter().take(advance_count).sum();
+        html.push_str("    <tr>\n");
+        html.push_str(&format!("        <td>{}</td>\n", escape(&result.team.0)));
+        html.push_str(&format!("        <td>{}</td>\n", result.current_point));
+        html.push_str(&format!("        <td>{:.1}</td>\n", result.expected_point));
+        html.push_str(&format!("        <td>{}</td>\n", result.current_battle));
+        html.push_str(&format!("        <td>{:.1}</td>\n", result.expected_battle));
+        html.push_str(&format!(
+            "        <td>{}</td>\n",
+            format_percentage(advance_probability)
+        ));
+        for probability in result.place_probabilities.iter() {
+            html.push_str(&format!(
+                "        <td>{}</td>\n",
+                format_percentage(*probability)
+            ));
+        }
+        html.push_str("    </tr>\n");
+    }
+    html.push_str("    </tbody>\n</table>\n");
+    html
+}
+
+fn render_rating_table(division: &SflDivision, results: &[SimulationResult]) -> String {
+    let mut html = format!(
+        "<h3>{}</h3>\n<table>\n    <thead>\n    <tr>\n        <th>チーム</th>\n",
+        division.sfl_stage.get_display_name()
+    );
+    if let Some(result) = results.first() {
+        for (name, _) in result.ratings.iter() {
+            html.push_str(&format!("        <th>{}</th>\n", get_rating_header(name)));
+        }
+    }
+    html.push_str("    </tr>\n    </thead>\n    <tbody>\n");
+    for result in results.iter() {
+        html.push_str("    <tr>\n");
+        html.push_str(&format!("        <td>{}</td>\n", escape(&result.team.0)));
+        for (_, rating) in result.ratings.iter() {
+            html.push_str(&format!("        <td>{}</td>\n", rating.round()));
+        }
+        html.push_str("    </tr>\n");
+    }
+    html.push_str("    </tbody>\n</table>\n");
+    html
+}
+
+fn render_setting_form(setting: &SimulateSetting) -> String {
+    let (is_rating, is_home_away, is_game_type) = setting.sfl_rate_setting.to_flags();
+    let checked = |flag: bool| if flag { " checked" } else { "" };
+    format!(
+        "<h2>予想設定</h2>
+<ul>
+    <li>
+        <label>
+            <input type=\"checkbox\" name=\"rating\"{}>
+            レートを計算して確率に反映する
+        </label>
+    </li>
+    <li>
+        <ul>
+            <li>
+                <label>
+                    <input type=\"checkbox\" name=\"home_away\"{}>
+                    AWAY / HOMEを区別してレートを計算する
+                </label>
+            </li>
+            <li>
+                <label>
+                    <input type=\"checkbox\" name=\"game_type\"{}>
+                    先鋒戦・中堅戦と大将戦・延長戦を区別してレートを計算する
+                </label>
+            </li>
+        </ul>
+    </li>
+</ul>
+<span>※レートは全てバトル単位で計算します。（バトル得失まで予想します）</span>
+",
+        checked(is_rating),
+        checked(is_home_away),
+        checked(is_game_type)
+    )
+}
+
+// 結果入力フォームのラジオボタンの name
+// ディビジョンのキー、ディビジョン内の試合の通し番号、戦のキー、セット番号をつなげる（s-1-van-1 など）
+pub fn get_set_input_name(
+    division: &SflDivision,
+    index: usize,
+    set_number: u32,
+    game_type: &GameType,
+) -> String {
+    format!(
+        "{}-{}-{}-{}",
+        division.sfl_stage.get_form_key(),
+        index + 1,
+        game_type.get_form_key(),
+        set_number
+    )
+}
+
+// 1試合分の入力欄。team の行で選ぶと win、opponent_team の行で選ぶと lose が送られる
+fn render_match_form(division: &SflDivision, index: usize) -> String {
+    let sfl_match = &division.matches[index];
+    let match_format = division.sfl_stage.get_match_format();
+    let games = match_format.get_games();
+    let played_records = division.get_played_records(index);
+    let (team_point, opponent_team_point) = division.get_played_points(index);
+    let mut html = format!(
+        "<table>\n    <tbody>\n    <tr>\n        <th colspan=\"2\">第{}節 第{}試合</th>\n",
+        sfl_match.section, sfl_match.branch
+    );
+    for bout in match_format
+        .bouts
+        .iter()
+        .chain(match_format.tiebreak.iter())
+    {
+        html.push_str(&format!(
+            "        <th colspan=\"{}\">{}</th>\n",
+            bout.get_max_set_count(),
+            bout.game_type.get_display_name()
+        ));
+    }
+    html.push_str("        <th>ポイント</th>\n    </tr>\n");
+    let rows = [
+        (&sfl_match.team, sfl_match.is_home, true, team_point),
+        (
+            &sfl_match.opponent_team,
+            !sfl_match.is_home,
+            false,
+            opponent_team_point,
+        ),
+    ];
+    for (team, is_home, win_flag, point) in rows.into_iter() {
+        html.push_str(&format!(
+            "    <tr>\n        <th>{}</th>\n        <th>{}</th>\n",
+            escape(&team.0),
+            if is_home { "HOME" } else { "AWAY" }
+        ));
+        for (set_index, (set_number, game_type)) in games.iter().enumerate() {
+            let record = &played_records[set_index];
+            let is_checked = record.is_valid && record.win_flag == win_flag;
+            html.push_str(&format!(
+                "        <td>\n            <label>\n                <input type=\"radio\" name=\"{}\" value=\"{}\"{}>\n            </label>\n        </td>\n",
+                get_set_input_name(division, index, *set_number, game_type),
+                if win_flag { "win" } else { "lose" },
+                if is_checked { " checked" } else { "" }
+            ));
+        }
+        html.push_str(&format!("        <td>{}</td>\n    </tr>\n", point));
+    }
+    html.push_str("    </tbody>\n</table>\n");
+    html
+}
+
+// SflRatingSetting::get_rating_keys の表示名を表の見出しにする
+fn get_rating_header(name: &str) -> String {
+    let game_type = if name.starts_with("MM") {
+        Some("先鋒・中堅")
+    } else if name.starts_with("LD") {
+        Some("大将・延長")
+    } else {
+        None
+    };
+    let home_away = if name.ends_with("AW") {
+        Some("AWAY")
+    } else if name.ends_with("HM") {
+        Some("HOME")
+    } else {
+        None
+    };
+    match (game_type, home_away) {
+        (Some(game_type), Some(home_away)) => format!("{}<br>{}", game_type, home_away),
+        (Some(game_type), None) => game_type.to_string(),
+        (None, Some(home_away)) => home_away.to_string(),
+        (None, None) => "レート".to_string(),
+    }
+}
+
+pub fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
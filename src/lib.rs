@@ -0,0 +1,12 @@
+pub mod csv;
+pub mod elo;
+pub mod glicko;
+pub mod html;
+pub mod report;
+pub mod season;
+pub mod server;
+pub mod sfl;
+pub mod simulate;
+pub mod startgg;
+pub mod stats;
+pub mod team_rating;
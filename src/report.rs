@@ -0,0 +1,104 @@
+use crate::sfl::SflDivision;
+use crate::simulate::{SeasonSimulationResult, SimulationResult};
+
+// シミュレーション結果をタブ区切りの表にする
+
+// 小数点第一位までのパーセント表示
+pub fn format_percentage(probability: f64) -> String {
+    format!("{:.1}%", probability * 100_f64)
+}
+
+pub fn format_places(division: &SflDivision, results: &[SimulationResult]) -> String {
+    let mut lines: Vec<String> = vec![format!("{:?}", division.sfl_stage)];
+    let place_header = (1..=division.teams.len())
+        .map(|n| format!("{}位", n))
+        .collect::<Vec<String>>()
+        .join("\t");
+    lines.push(format!(
+        "TEAM\tPT現在\tPT予想\t得失現在\t得失予想\t{}",
+        place_header
+    ));
+    for result in results.iter() {
+        let places_text = result
+            .place_probabilities
+            .iter()
+            .map(|probability| format_percentage(*probability))
+            .collect::<Vec<String>>()
+            .join("\t");
+        lines.push(format!(
+            "{}\t{}\t{:.1}\t{}\t{:.1}\t{}",
+            result.team,
+            result.current_point,
+            result.expected_point,
+            result.current_battle,
+            result.expected_battle,
+            places_text
+        ));
+    }
+    lines.join("\n")
+}
+
+pub fn format_ratings(division: &SflDivision, results: &[SimulationResult]) -> String {
+    let mut lines: Vec<String> = vec![format!("{:?}", division.sfl_stage)];
+    let rating_header = results
+        .first()
+        .map(|result| {
+            result
+                .ratings
+                .iter()
+                .map(|(name, _)| name.to_string())
+                .collect::<Vec<String>>()
+                .join("\t")
+        })
+        .unwrap_or_default();
+    lines.push(format!("TEAM\t{}", rating_header));
+    for result in results.iter() {
+        let rating_text = result
+            .ratings
+            .iter()
+            .map(|(_, rating)| rating.round().to_string())
+            .collect::<Vec<String>>()
+            .join("\t");
+        lines.push(format!("{}\t{}", result.team, rating_text));
+    }
+    lines.join("\n")
+}
+
+pub fn format_standings(division: &SflDivision, results: &[SimulationResult]) -> String {
+    // ポイント、バトル得失の順に並べる
+    let mut standings: Vec<&SimulationResult> = results.iter().collect();
+    standings.sort_by(|a, b| {
+        b.current_point
+            .cmp(&a.current_point)
+            .then(b.current_battle.cmp(&a.current_battle))
+            .then(
+                division
+                    .get_team_order(&b.team)
+                    .cmp(&division.get_team_order(&a.team)),
+            )
+    });
+    let mut lines: Vec<String> = vec![
+        format!("{:?}", division.sfl_stage),
+        "TEAM\tPOINT\tBATTLE".to_string(),
+    ];
+    for result in standings.into_iter() {
+        lines.push(format!(
+            "{}\t{}\t{}",
+            result.team, result.current_point, result.current_battle
+        ));
+    }
+    lines.join("\n")
+}
+
+pub fn format_rounds(result: &SeasonSimulationResult) -> String {
+    let mut lines: Vec<String> = vec![format!("TEAM\t{}", result.round_names.join("\t"))];
+    for (team, probabilities) in result.round_probabilities.iter() {
+        let probabilities_text = probabilities
+            .iter()
+            .map(|probability| format_percentage(*probability))
+            .collect::<Vec<String>>()
+            .join("\t");
+        lines.push(format!("{}\t{}", team, probabilities_text));
+    }
+    lines.join("\n")
+}
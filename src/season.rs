@@ -0,0 +1,154 @@
+use crate::sfl::{SflDivision, SflMatch, SflSetPlayers, SflStage, SflTeam};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs;
+
+// シーズン定義ファイル（JSON）の形式
+// 新しいシーズンはファイルを用意するだけでシミュレーションできる
+#[derive(Deserialize)]
+pub struct SeasonFile {
+    pub name: String,
+    pub divisions: Vec<DivisionFile>,
+}
+
+#[derive(Deserialize)]
+pub struct DivisionFile {
+    pub stage: SflStage,
+    pub teams: Vec<SflTeam>,
+    pub matches: Vec<MatchFile>,
+    // チームの登録選手（SflRatingSetting::Player で使う）
+    #[serde(default)]
+    pub rosters: HashMap<SflTeam, Vec<String>>,
+}
+
+#[derive(Deserialize)]
+pub struct MatchFile {
+    pub section: u32,
+    pub branch: u32,
+    pub team: SflTeam,
+    pub opponent_team: SflTeam,
+    // ホームのチーム。team か opponent_team のどちらか（公式の対戦カードは AWAY を先に書くので opponent_team になる）
+    pub home: SflTeam,
+    // 結果の記録にあるホームのチーム。対戦カードのホームと一致するか確認する
+    // 結果の記録にホームが書かれていなければ省く
+    #[serde(default)]
+    pub results_home: Option<SflTeam>,
+    // すでに行われたセットの勝敗（team 側から見て勝ちなら true）
+    #[serde(default)]
+    pub results: Vec<bool>,
+    // セットごとの選手とキャラクター（キャラクターは省略できる）。わからないセットは null
+    // results より後のセットに書いた選手は出場予定として予想に使う
+    #[serde(default)]
+    pub players: Vec<Option<SflSetPlayers>>,
+}
+
+#[derive(Clone)]
+pub struct SflSeason {
+    pub name: String,
+    pub divisions: Vec<SflDivision>,
+}
+
+impl SflSeason {
+    pub fn find_division(&self, sfl_stage: SflStage) -> Option<&SflDivision> {
+        self.divisions.iter().find(|d| d.sfl_stage == sfl_stage)
+    }
+}
+
+pub fn load_season(path: &str) -> Result<SflSeason, String> {
+    let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+    parse_season(&text).map_err(|e| format!("{}: {}", path, e))
+}
+
+pub fn parse_season(text: &str) -> Result<SflSeason, String> {
+    let season_file: SeasonFile = serde_json::from_str(text).map_err(|e| e.to_string())?;
+    let mut divisions: Vec<SflDivision> = vec![];
+    for division_file in season_file.divisions.into_iter() {
+        divisions.push(division_file.into_division()?);
+    }
+    Ok(SflSeason {
+        name: season_file.name,
+        divisions,
+    })
+}
+
+impl DivisionFile {
+    fn into_division(self) -> Result<SflDivision, String> {
+        let DivisionFile {
+            stage: sfl_stage,
+            teams,
+            matches,
+            rosters,
+        } = self;
+        for team in rosters.keys() {
+            if !teams.contains(team) {
+                return Err(format!("{:?}: 登録選手に不明なチーム {}", sfl_stage, team));
+            }
+        }
+        let mut sfl_matches: Vec<SflMatch> = vec![];
+        let mut played_match_results: Vec<Vec<bool>> = vec![];
+        let mut match_players: Vec<Vec<Option<SflSetPlayers>>> = vec![];
+        for match_file in matches.into_iter() {
+            for team in [&match_file.team, &match_file.opponent_team] {
+                if !teams.contains(team) {
+                    return Err(format!(
+                        "{:?} {}節 第{}試合: 不明なチーム {}",
+                        sfl_stage, match_file.section, match_file.branch, team
+                    ));
+                }
+            }
+            if match_file.home != match_file.team && match_file.home != match_file.opponent_team {
+                return Err(format!(
+                    "{:?} {}節 第{}試合: ホーム {} が対戦チームに含まれていない",
+                    sfl_stage, match_file.section, match_file.branch, match_file.home
+                ));
+            }
+            if let Some(ref results_home) = match_file.results_home {
+                if *results_home != match_file.home {
+                    return Err(format!(
+                        "{:?} {}節 第{}試合: 結果のホーム {} が対戦カードのホーム {} と異なる",
+                        sfl_stage,
+                        match_file.section,
+                        match_file.branch,
+                        results_home,
+                        match_file.home
+                    ));
+                }
+            }
+            let is_home = match_file.home == match_file.team;
+            let sfl_match = SflMatch {
+                section: match_file.section,
+                branch: match_file.branch,
+                sfl_stage,
+                team: match_file.team,
+                opponent_team: match_file.opponent_team,
+                is_home,
+            };
+            // 結果の数がセット数を超えていないか確認
+            let set_count = sfl_stage.get_set_count();
+            if match_file.results.len() > set_count {
+                return Err(format!(
+                    "{:?} {}節 第{}試合: 結果が{}セットを超えている",
+                    sfl_stage, sfl_match.section, sfl_match.branch, set_count
+                ));
+            }
+            if match_file.players.len() > set_count {
+                return Err(format!(
+                    "{:?} {}節 第{}試合: 選手が{}セットを超えている",
+                    sfl_stage, sfl_match.section, sfl_match.branch, set_count
+                ));
+            }
+            // 未実施の試合は空の結果として、対戦カードと同じ並びで保持する
+            played_match_results.push(match_file.results);
+            match_players.push(match_file.players);
+            sfl_matches.push(sfl_match);
+        }
+        Ok(SflDivision {
+            sfl_stage,
+            teams,
+            matches: sfl_matches,
+            played_match_results,
+            match_players,
+            rosters,
+        })
+    }
+}
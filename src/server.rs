@@ -0,0 +1,235 @@
+use crate::html::{escape, get_set_input_name, render_page};
+use crate::season::SflSeason;
+use crate::sfl::{SflDivision, SflRatingSetting};
+use crate::simulate::{get_simulate_result, SimulateSetting, SimulationResult};
+use std::collections::HashMap;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::time::Duration;
+
+// sfl.html の入力フォームを受けて再計算するローカルサーバー
+// GET / はシーズンファイルの結果で、POST / はフォームで送られた結果と予想設定でページを返す
+// リクエストは1件ずつ順番に処理する
+
+// フォームの本文の上限（全ディビジョンの入力でも数十 KB）
+const MAX_BODY_LENGTH: usize = 1024 * 1024;
+// 送ってこないクライアントで待ち続けないように
+const STREAM_TIMEOUT: Duration = Duration::from_secs(10);
+
+pub fn serve(address: &str, season: &SflSeason, setting: &SimulateSetting) -> io::Result<()> {
+    let listener = TcpListener::bind(address)?;
+    eprintln!("http://{}/ で待ち受け中", listener.local_addr()?);
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(e) => {
+                eprintln!("{}", e);
+                continue;
+            }
+        };
+        // 1件の失敗でサーバーを止めない
+        if let Err(e) = handle_connection(stream, season, setting) {
+            eprintln!("{}", e);
+        }
+    }
+    Ok(())
+}
+
+fn handle_connection(
+    mut stream: TcpStream,
+    season: &SflSeason,
+    setting: &SimulateSetting,
+) -> io::Result<()> {
+    stream.set_read_timeout(Some(STREAM_TIMEOUT))?;
+    stream.set_write_timeout(Some(STREAM_TIMEOUT))?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    let mut parts = request_line.split_whitespace();
+    let method = parts.next().unwrap_or("");
+    let path = parts.next().unwrap_or("");
+
+    let mut content_length: usize = 0;
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 {
+            break;
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                content_length = value.trim().parse().unwrap_or(0);
+            }
+        }
+    }
+    if content_length > MAX_BODY_LENGTH {
+        return write_response(
+            &mut stream,
+            "413 Payload Too Large",
+            "<h1>413 Payload Too Large</h1>\n",
+        );
+    }
+    let mut body = vec![0; content_length];
+    reader.read_exact(&mut body)?;
+
+    let (status, html) = match (method, path) {
+        ("GET", "/") => ("200 OK", render_result_page(season, setting)),
+        ("POST", "/") => {
+            let form = parse_form(&String::from_utf8_lossy(&body));
+            match apply_form(season, setting, &form) {
+                Ok((season, setting)) => ("200 OK", render_result_page(&season, &setting)),
+                Err(e) => (
+                    "400 Bad Request",
+                    format!(
+                        "<h1>400 Bad Request</h1>\n<p>{}</p>\n<p><a href=\"/\">戻る</a></p>\n",
+                        escape(&e)
+                    ),
+                ),
+            }
+        }
+        ("GET", _) | ("POST", _) => ("404 Not Found", "<h1>404 Not Found</h1>\n".to_string()),
+        _ => (
+            "405 Method Not Allowed",
+            "<h1>405 Method Not Allowed</h1>\n".to_string(),
+        ),
+    };
+    write_response(&mut stream, status, &html)
+}
+
+fn write_response(stream: &mut TcpStream, status: &str, html: &str) -> io::Result<()> {
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: text/html; charset=utf-8\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        status,
+        html.len()
+    )?;
+    stream.write_all(html.as_bytes())?;
+    stream.flush()
+}
+
+fn render_result_page(season: &SflSeason, setting: &SimulateSetting) -> String {
+    let division_results: Vec<(&SflDivision, Vec<SimulationResult>)> = season
+        .divisions
+        .iter()
+        .map(|division| (division, get_simulate_result(division, setting)))
+        .collect();
+    render_page(season, setting, &division_results, Some("/"))
+}
+
+// フォームの入力でシーズンの試合結果と予想設定を置き換える
+// 決着後で行われないセットは空でよいが、行われるはずのセットが空のまま後のセットが入力されていればエラー
+fn apply_form(
+    season: &SflSeason,
+    setting: &SimulateSetting,
+    form: &HashMap<String, String>,
+) -> Result<(SflSeason, SimulateSetting), String> {
+    let mut season = season.clone();
+    for division in season.divisions.iter_mut() {
+        let games = division.sfl_stage.get_match_format().get_games();
+        for index in 0..division.matches.len() {
+            let win_flags: Vec<Option<bool>> = games
+                .iter()
+                .map(|(set_number, game_type)| {
+                    let name = get_set_input_name(division, index, *set_number, game_type);
+                    match form.get(&name).map(|value| value.as_str()) {
+                        Some("win") => Some(true),
+                        Some("lose") => Some(false),
+                        _ => None,
+                    }
+                })
+                .collect();
+            division.played_match_results[index] = get_played_results(division, index, &win_flags)?;
+        }
+    }
+    let mut setting = setting.to_owned();
+    setting.sfl_rate_setting = SflRatingSetting::from_flags(
+        form.contains_key("rating"),
+        form.contains_key("home_away"),
+        form.contains_key("game_type"),
+    );
+    Ok((season, setting))
+}
+
+// 入力されたセットの結果を SflDivision::played_match_results の並びにする
+// 行われないセットは false で埋める（correct_records で無効になる）
+fn get_played_results(
+    division: &SflDivision,
+    index: usize,
+    win_flags: &[Option<bool>],
+) -> Result<Vec<bool>, String> {
+    let sfl_stage = division.sfl_stage;
+    let sfl_match = &division.matches[index];
+    let games = sfl_stage.get_match_format().get_games();
+    let played_count = win_flags
+        .iter()
+        .rposition(|win_flag| win_flag.is_some())
+        .map_or(0, |position| position + 1);
+    let mut results: Vec<bool> = vec![];
+    for (set_index, win_flag) in win_flags.iter().take(played_count).enumerate() {
+        if let Some(win_flag) = win_flag {
+            results.push(*win_flag);
+            continue;
+        }
+        // そこまでの結果でこのセットが行われるかを確かめる
+        let mut records = sfl_stage.match_to_records(sfl_match);
+        for (record, win_flag) in records
+            .iter_mut()
+            .zip(results.iter().chain(std::iter::once(&false)))
+        {
+            record.win_flag = *win_flag;
+            record.is_valid = true;
+        }
+        sfl_stage.correct_records(&mut records);
+        if records[set_index].is_valid {
+            let (set_number, ref game_type) = games[set_index];
+            return Err(format!(
+                "{} {}節 第{}試合: {}の{}セット目が未入力のまま、後のセットが入力されている",
+                sfl_stage.get_display_name(),
+                sfl_match.section,
+                sfl_match.branch,
+                game_type.get_display_name(),
+                set_number
+            ));
+        }
+        results.push(false);
+    }
+    Ok(results)
+}
+
+// application/x-www-form-urlencoded の本文を読む
+fn parse_form(body: &str) -> HashMap<String, String> {
+    body.split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (name, value) = pair.split_once('=').unwrap_or((pair, ""));
+            (decode_form_component(name), decode_form_component(value))
+        })
+        .collect()
+}
+
+fn decode_form_component(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut decoded: Vec<u8> = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'+' => decoded.push(b' '),
+            b'%' if i + 2 < bytes.len() => {
+                let hex = std::str::from_utf8(&bytes[i + 1..i + 3]).unwrap_or("");
+                match u8::from_str_radix(hex, 16) {
+                    Ok(byte) => {
+                        decoded.push(byte);
+                        i += 2;
+                    }
+                    Err(_) => decoded.push(b'%'),
+                }
+            }
+            byte => decoded.push(byte),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
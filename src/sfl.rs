@@ -1,9 +1,11 @@
 use crate::sfl::GameType::{EXTRA, GENERAL, MID, VAN};
-use crate::sfl::SflStage::{JP2024DivisionF, JP2024DivisionS};
-use crate::sfl::SflTeam::*;
+use crate::sfl::SflStage::{JP2024DivisionF, JP2024DivisionS, JP2024GrandFinal, JP2024Playoff};
+use serde::Deserialize;
 use std::cmp::PartialEq;
 use std::collections::HashMap;
 use std::fmt;
+use std::ops::RangeInclusive;
+use std::str::FromStr;
 
 #[derive(Clone, Debug)]
 pub struct SflRecord {
@@ -19,6 +21,20 @@ pub struct SflRecord {
     // 予想か実際かを区別する。予想ならtrue
     // これから予想する場合にもtrue。実績ならfalse
     pub is_prediction: bool,
+    // セットに出た（予想なら出る予定の）選手。わからなければ None
+    pub players: Option<SflSetPlayers>,
+}
+
+// 1セットに出た選手とキャラクター。player, character が team 側
+// キャラクターは記録用で、選手のレートには使わない
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+pub struct SflSetPlayers {
+    pub player: String,
+    #[serde(default)]
+    pub character: Option<String>,
+    pub opponent_player: String,
+    #[serde(default)]
+    pub opponent_character: Option<String>,
 }
 
 impl SflRecord {
@@ -83,7 +99,8 @@ impl SflRecord {
     // }
 }
 
-#[derive(Clone, Debug)]
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub enum GameType {
     VAN,
     MID,
@@ -92,44 +109,190 @@ pub enum GameType {
 }
 
 impl GameType {
-    fn get_point(&self) -> u32 {
-        match self {
-            VAN => 10,
-            MID => 10,
-            GENERAL => 20,
-            EXTRA => 5,
-        }
-    }
     fn is_leader(&self) -> bool {
         match self {
             VAN | MID => false,
             GENERAL | EXTRA => true,
         }
     }
-    fn get_games_by_stage(sfl_stage: &SflStage) -> Vec<(u32, GameType)> {
-        match sfl_stage {
-            JP2024DivisionS | JP2024DivisionF => {
-                vec![
-                    (1, VAN),
-                    (2, VAN),
-                    (3, VAN),
-                    (1, MID),
-                    (2, MID),
-                    (3, MID),
-                    (1, GENERAL),
-                    (2, GENERAL),
-                    (3, GENERAL),
-                    (4, GENERAL),
-                    (5, GENERAL),
-                    (1, EXTRA),
-                ]
+    // 結果入力フォームの name に使うキー
+    pub fn get_form_key(&self) -> &'static str {
+        match self {
+            VAN => "van",
+            MID => "mid",
+            GENERAL => "general",
+            EXTRA => "extra",
+        }
+    }
+    pub fn get_display_name(&self) -> &'static str {
+        match self {
+            VAN => "先鋒戦",
+            MID => "中堅戦",
+            GENERAL => "大将戦",
+            EXTRA => "延長戦",
+        }
+    }
+}
+
+// 戦（先鋒戦、中堅戦など）のルール
+// required_win 本先取で決着し、決着したセットに point が付く
+#[derive(Clone, Debug)]
+pub struct SflBoutRule {
+    pub game_type: GameType,
+    pub required_win: u32,
+    pub point: u32,
+}
+
+impl SflBoutRule {
+    pub fn new(game_type: GameType, required_win: u32, point: u32) -> SflBoutRule {
+        SflBoutRule {
+            game_type,
+            required_win,
+            point,
+        }
+    }
+    pub fn get_max_set_count(&self) -> usize {
+        self.required_win as usize * 2 - 1
+    }
+}
+
+// 1試合のルール
+// レコードは bouts の順、最後に tiebreak の順で並ぶ
+#[derive(Clone, Debug)]
+pub struct SflMatchFormat {
+    pub bouts: Vec<SflBoutRule>,
+    // 勝ち抜けに必要な戦の数。決着後の戦は行わない
+    // None ならすべての戦を行ってポイントを競う
+    pub required_bout: Option<u32>,
+    // すべての戦が決着してポイントが並んだときに行う戦
+    pub tiebreak: Option<SflBoutRule>,
+}
+
+impl SflMatchFormat {
+    pub fn get_games(&self) -> Vec<(u32, GameType)> {
+        self.bouts
+            .iter()
+            .chain(self.tiebreak.iter())
+            .flat_map(|bout| {
+                (1..=bout.get_max_set_count() as u32).map(|n| (n, bout.game_type.to_owned()))
+            })
+            .collect()
+    }
+    // すべてのセットが行われたときの、1試合で両チームに入るポイントの合計の範囲
+    pub fn get_point_range(&self) -> RangeInclusive<u32> {
+        let point: u32 = match self.required_bout {
+            // 勝ち抜けが決まった時点で終わるので、ポイントの小さい戦から勝ち抜けた場合が最小
+            Some(required_bout) => {
+                let mut points: Vec<u32> = self.bouts.iter().map(|bout| bout.point).collect();
+                points.sort();
+                points.iter().take(required_bout as usize).sum()
             }
-            _ => vec![],
+            None => self.bouts.iter().map(|bout| bout.point).sum(),
+        };
+        let max_point: u32 = match self.required_bout {
+            Some(_) => self.bouts.iter().map(|bout| bout.point).sum(),
+            None => point,
+        } + self.tiebreak.as_ref().map_or(0, |bout| bout.point);
+        point..=max_point
+    }
+    // is_valid = true フラグが立っているレコードについて見直して一部 is_valid = false に変える
+    // ポイントを決着セットに書き加える
+    // 決着していない場合はもちろんポイントを書かない
+    // ランダム結果と実際結果が混じることがある
+    pub fn correct_records(&self, records: &mut [SflRecord]) {
+        let mut team_point: u32 = 0;
+        let mut opponent_team_point: u32 = 0;
+        let mut team_bout: u32 = 0;
+        let mut opponent_team_bout: u32 = 0;
+        let mut offset: usize = 0;
+        for bout in self.bouts.iter() {
+            let bout_records = &mut records[offset..offset + bout.get_max_set_count()];
+            offset += bout.get_max_set_count();
+            // 勝ち抜けが決まった後の戦は行わない
+            if let Some(required_bout) = self.required_bout {
+                if team_bout == required_bout || opponent_team_bout == required_bout {
+                    for record in bout_records.iter_mut() {
+                        record.is_valid = false;
+                    }
+                    continue;
+                }
+            }
+            match correct_bout(bout, bout_records) {
+                Some(true) => {
+                    team_point += bout.point;
+                    team_bout += 1;
+                }
+                Some(false) => {
+                    opponent_team_point += bout.point;
+                    opponent_team_bout += 1;
+                }
+                None => {}
+            }
+        }
+
+        // 延長戦
+        if let Some(ref tiebreak) = self.tiebreak {
+            let tiebreak_records = &mut records[offset..offset + tiebreak.get_max_set_count()];
+            let is_all_decided = team_bout + opponent_team_bout == self.bouts.len() as u32;
+            if is_all_decided && team_point == opponent_team_point {
+                correct_bout(tiebreak, tiebreak_records);
+            } else {
+                for record in tiebreak_records.iter_mut() {
+                    record.is_valid = false;
+                }
+            }
+        }
+    }
+    // 勝ち抜けに必要な戦の数を取ったチーム
+    // team が勝てば Some(true)、opponent_team が勝てば Some(false)、決着していなければ None
+    // correct_records で決着セットにポイントが付いていることが前提
+    pub fn get_winner(&self, records: &[SflRecord]) -> Option<bool> {
+        let required_bout = self.required_bout?;
+        let decided_records: Vec<&SflRecord> = records
+            .iter()
+            .filter(|r| r.is_valid && r.point != 0)
+            .collect();
+        let team_bout = decided_records.iter().filter(|r| r.win_flag).count() as u32;
+        let opponent_team_bout = decided_records.len() as u32 - team_bout;
+        if team_bout >= required_bout {
+            Some(true)
+        } else if opponent_team_bout >= required_bout {
+            Some(false)
+        } else {
+            None
         }
     }
 }
 
-#[derive(Clone, Copy, Debug)]
+// 1つの戦のセットを順に見て、先取したセットにポイントを付け、以降のセットを is_valid = false にする
+// まだ行われていないセット（is_valid = false）があればそこで止めて未決着とする
+fn correct_bout(bout: &SflBoutRule, records: &mut [SflRecord]) -> Option<bool> {
+    let mut team_win: u32 = 0;
+    let mut opponent_team_win: u32 = 0;
+    let mut winner: Option<bool> = None;
+    for record in records.iter_mut() {
+        if winner.is_some() {
+            // ポイントのリセットはここではしない
+            record.is_valid = false;
+            continue;
+        }
+        if !record.is_valid {
+            break;
+        }
+        if record.win_flag {
+            team_win += 1;
+        } else {
+            opponent_team_win += 1;
+        }
+        if team_win == bout.required_win || opponent_team_win == bout.required_win {
+            record.point = bout.point;
+            winner = Some(record.win_flag);
+        }
+    }
+    winner
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
 pub enum SflStage {
     JP2024DivisionS,
     JP2024DivisionF,
@@ -137,289 +300,191 @@ pub enum SflStage {
     JP2024GrandFinal,
 }
 
+impl FromStr for SflStage {
+    type Err = String;
+    fn from_str(s: &str) -> Result<SflStage, String> {
+        match s {
+            "JP2024DivisionS" => Ok(JP2024DivisionS),
+            "JP2024DivisionF" => Ok(JP2024DivisionF),
+            "JP2024Playoff" => Ok(JP2024Playoff),
+            "JP2024GrandFinal" => Ok(JP2024GrandFinal),
+            _ => Err(format!("不明なステージ: {}", s)),
+        }
+    }
+}
+
 impl SflStage {
-    pub fn get_teams(&self) -> Vec<SflTeam> {
+    pub fn get_match_format(&self) -> SflMatchFormat {
         match self {
-            JP2024DivisionS => vec![G8S, DFM, SOL, IBS, OJA, SNB],
-            JP2024DivisionF => vec![CR, CAG, IXA, RC, VAR, FAV],
-            _ => vec![],
+            // 先鋒・中堅は2本先取で10ポイント、大将は3本先取で20ポイント
+            // 20-20で並んだら延長戦（1本勝負、5ポイント）
+            JP2024DivisionS | JP2024DivisionF => SflMatchFormat {
+                bouts: vec![
+                    SflBoutRule::new(VAN, 2, 10),
+                    SflBoutRule::new(MID, 2, 10),
+                    SflBoutRule::new(GENERAL, 3, 20),
+                ],
+                required_bout: None,
+                tiebreak: Some(SflBoutRule::new(EXTRA, 1, 5)),
+            },
+            // プレイオフは先鋒・中堅が2本先取、大将が3本先取で、2つの戦を取ったチームが勝ち抜ける
+            JP2024Playoff => SflMatchFormat {
+                bouts: vec![
+                    SflBoutRule::new(VAN, 2, 10),
+                    SflBoutRule::new(MID, 2, 10),
+                    SflBoutRule::new(GENERAL, 3, 20),
+                ],
+                required_bout: Some(2),
+                tiebreak: None,
+            },
+            // グランドファイナルはすべて3本先取
+            JP2024GrandFinal => SflMatchFormat {
+                bouts: vec![
+                    SflBoutRule::new(VAN, 3, 10),
+                    SflBoutRule::new(MID, 3, 10),
+                    SflBoutRule::new(GENERAL, 3, 20),
+                ],
+                required_bout: Some(2),
+                tiebreak: None,
+            },
         }
     }
-    pub fn get_initial_records(&self) -> Vec<Vec<SflRecord>> {
-        self.get_matches()
-            .iter()
-            .map(|sfl_match| self.match_to_records(sfl_match))
-            .collect()
+    pub fn get_set_count(&self) -> usize {
+        self.get_match_format().get_games().len()
     }
-    pub fn get_matches(&self) -> Vec<SflMatch> {
+    // 勝ち抜いたチームが進むステージ
+    pub fn get_next_stage(&self) -> Option<SflStage> {
         match self {
-            JP2024DivisionS => {
-                vec![
-                    (1, 1, DFM, OJA),
-                    (1, 2, G8S, SNB),
-                    (1, 3, SOL, IBS),
-                    (2, 1, SNB, DFM),
-                    (2, 2, IBS, OJA),
-                    (2, 3, SOL, G8S),
-                    (3, 1, OJA, SOL),
-                    (3, 2, G8S, DFM),
-                    (3, 3, SNB, IBS),
-                    (4, 1, G8S, OJA),
-                    (4, 2, SNB, SOL),
-                    (4, 3, IBS, DFM),
-                    (5, 1, IBS, G8S),
-                    (5, 2, DFM, SOL),
-                    (5, 3, OJA, SNB),
-                    (6, 1, IBS, SOL),
-                    (6, 2, SNB, G8S),
-                    (6, 3, OJA, DFM),
-                    (7, 1, G8S, SOL),
-                    (7, 2, DFM, SNB),
-                    (7, 3, OJA, IBS),
-                    (8, 1, IBS, SNB),
-                    (8, 2, SOL, OJA),
-                    (8, 3, DFM, G8S),
-                    (9, 1, OJA, G8S),
-                    (9, 2, DFM, IBS),
-                    (9, 3, SOL, SNB),
-                    (10, 1, SNB, OJA),
-                    (10, 2, SOL, DFM),
-                    (10, 3, G8S, IBS),
-                ]
-            }
-            JP2024DivisionF => {
+            JP2024DivisionS | JP2024DivisionF => Some(JP2024Playoff),
+            JP2024Playoff => Some(JP2024GrandFinal),
+            JP2024GrandFinal => None,
+        }
+    }
+    // 結果入力フォームの name に使うキー
+    pub fn get_form_key(&self) -> &'static str {
+        match self {
+            JP2024DivisionS => "s",
+            JP2024DivisionF => "f",
+            JP2024Playoff => "po",
+            JP2024GrandFinal => "gf",
+        }
+    }
+    pub fn get_display_name(&self) -> &'static str {
+        match self {
+            JP2024DivisionS => "ディビジョンS",
+            JP2024DivisionF => "ディビジョンF",
+            JP2024Playoff => "プレイオフ",
+            JP2024GrandFinal => "グランドファイナル",
+        }
+    }
+    // ノックアウト方式の節の表示名
+    pub fn get_section_name(&self, section: u32) -> String {
+        match (self, section) {
+            (JP2024Playoff, 1) => "PO".to_string(),
+            (JP2024Playoff, 2) => "PO準決勝".to_string(),
+            (JP2024GrandFinal, 1) => "GF".to_string(),
+            _ => format!("{:?}-{}", self, section),
+        }
+    }
+    // ノックアウト方式（勝ち抜いたチームが次のステージに進む）かどうか
+    pub fn is_knockout(&self) -> bool {
+        match self {
+            JP2024DivisionS | JP2024DivisionF => false,
+            JP2024Playoff | JP2024GrandFinal => true,
+        }
+    }
+    // トーナメントの組み合わせ
+    // 前のステージから進んだチームのシード順（get_knockout_seeds）を SflSlot::Seed で参照する
+    pub fn get_bracket(&self) -> Vec<SflBracketMatch> {
+        match self {
+            JP2024DivisionS | JP2024DivisionF => vec![],
+            // シードは S1, S2, S3, F1, F2, F3 の順
+            // 1回戦で各ディビジョン2位と他ディビジョン3位が対戦し、準決勝で各ディビジョン1位と対戦する
+            JP2024Playoff => {
                 vec![
-                    (1, 1, RC, IXA),
-                    (1, 2, CAG, VAR),
-                    (1, 3, CR, FAV),
-                    (2, 1, VAR, RC),
-                    (2, 2, FAV, IXA),
-                    (2, 3, CR, CAG),
-                    (3, 1, IXA, CR),
-                    (3, 2, CAG, RC),
-                    (3, 3, VAR, FAV),
-                    (4, 1, CAG, IXA),
-                    (4, 2, VAR, CR),
-                    (4, 3, FAV, RC),
-                    (5, 1, FAV, CAG),
-                    (5, 2, RC, CR),
-                    (5, 3, IXA, VAR),
-                    (6, 1, FAV, CR),
-                    (6, 2, VAR, CAG),
-                    (6, 3, IXA, RC),
-                    (7, 1, CAG, CR),
-                    (7, 2, RC, VAR),
-                    (7, 3, IXA, FAV),
-                    (8, 1, FAV, VAR),
-                    (8, 2, CR, IXA),
-                    (8, 3, RC, CAG),
-                    (9, 1, IXA, CAG),
-                    (9, 2, RC, FAV),
-                    (9, 3, CR, VAR),
-                    (10, 1, VAR, IXA),
-                    (10, 2, CR, RC),
-                    (10, 3, CAG, FAV),
+                    (1, 1, SflSlot::Seed(1), SflSlot::Seed(5)),
+                    (1, 2, SflSlot::Seed(4), SflSlot::Seed(2)),
+                    (2, 1, SflSlot::Seed(0), SflSlot::Winner(1, 2)),
+                    (2, 2, SflSlot::Seed(3), SflSlot::Winner(1, 1)),
                 ]
             }
-            _ => {
-                vec![]
-            }
+            // シードはプレイオフ準決勝の勝者
+            JP2024GrandFinal => vec![(1, 1, SflSlot::Seed(0), SflSlot::Seed(1))],
         }
-        .iter()
-        .map(|tup| {
-            let (section, branch, team, opponent_team) = tup.to_owned();
-            SflMatch {
-                section,
-                branch,
-                sfl_stage: self.to_owned(),
-                team,
-                opponent_team,
-                is_home: false,
-            }
+        .into_iter()
+        .map(|(section, branch, slot, opponent_slot)| SflBracketMatch {
+            section,
+            branch,
+            slot,
+            opponent_slot,
         })
         .collect()
     }
-    fn match_to_records(&self, sfl_match: &SflMatch) -> Vec<SflRecord> {
-        match sfl_match.sfl_stage {
-            JP2024DivisionS | JP2024DivisionF => {
-                GameType::get_games_by_stage(&sfl_match.sfl_stage)
-                    .iter()
-                    .map(|(set_number, game_type)| {
-                        SflRecord {
-                            sfl_match: sfl_match.to_owned(),
-                            set_number: *set_number,
-                            win_flag: false,
-                            game_type: game_type.to_owned(),
-                            // pointはcorrect_recordでセットする
-                            point: 0,
-                            is_valid: false,
-                            is_prediction: true,
-                        }
-                    })
-                    .collect()
-            }
-            _ => vec![],
+    // 前のステージの各グループ（ディビジョン、試合）からこのステージに進むチーム数
+    // ディビジョンの上位3チームがプレイオフへ、プレイオフの準決勝の勝者がグランドファイナルへ進む
+    pub fn get_advance_count(&self) -> usize {
+        match self {
+            JP2024DivisionS | JP2024DivisionF => 0,
+            JP2024Playoff => 3,
+            JP2024GrandFinal => 1,
         }
     }
-    // パフォーマンスの問題もあるから前後の関連だけ見て修正する
-    // is_valid = true フラグが立っているレコードについて見直して一部 is_valid = false に変える
-    // ポイントを決着セットに書き加える
-    // 決着していない場合はもちろんポイントを書かない
-    // ランダム結果と実際結果が混じることがある
-    pub fn correct_records(&self, records: &mut Vec<SflRecord>) {
+    // シードを出す前のステージのグループ（ディビジョン）とその順番。前のステージがノックアウト方式なら空
+    pub fn get_seed_stages(&self) -> Vec<SflStage> {
         match self {
-            JP2024DivisionS | JP2024DivisionF => {
-                let van1 = records.get(0).unwrap().to_owned();
-                let van2 = records.get(1).unwrap().to_owned();
-                let van3 = records.get(2).unwrap().to_owned();
-                let mid1 = records.get(3).unwrap().to_owned();
-                let mid2 = records.get(4).unwrap().to_owned();
-                let mid3 = records.get(5).unwrap().to_owned();
-                let general1 = records.get(6).unwrap().to_owned();
-                let general2 = records.get(7).unwrap().to_owned();
-                let general3 = records.get(8).unwrap().to_owned();
-                let general4 = records.get(9).unwrap().to_owned();
-                let general5 = records.get(10).unwrap().to_owned();
-
-                let mut team_point: u32 = 0;
-                let mut opponent_team_point: u32 = 0;
-
-                // 先鋒戦のポイント決定
-                let van_point = VAN.get_point();
-                if van1.is_valid && van2.is_valid {
-                    if van1.win_flag == van2.win_flag {
-                        let mut_van2 = records.get_mut(1).unwrap();
-                        mut_van2.point = van_point;
-                        let mut_van3 = records.get_mut(2).unwrap();
-                        mut_van3.is_valid = false;
-                        // ポイントのリセットはここではしない
-                        // mut_van3.point = 0;
-                        if van1.win_flag {
-                            team_point += van_point;
-                        } else {
-                            opponent_team_point += van_point;
-                        }
-                    } else if van3.is_valid {
-                        let mut_van3 = records.get_mut(2).unwrap();
-                        mut_van3.is_valid = true;
-                        mut_van3.point = van_point;
-                        // ポイントのリセットはここではしない
-                        // let mut_van2 = records.get_mut(1).unwrap();
-                        // mut_van2.point = 0;
-                        if van3.win_flag {
-                            team_point += van_point;
-                        } else {
-                            opponent_team_point += van_point;
-                        }
-                    }
-                }
-
-                // 中堅戦のポイント決定
-                let mid_point = MID.get_point();
-                if mid1.is_valid && mid2.is_valid {
-                    if mid1.win_flag == mid2.win_flag {
-                        let mut_mid2 = records.get_mut(4).unwrap();
-                        mut_mid2.point = mid_point;
-                        let mut_mid3 = records.get_mut(5).unwrap();
-                        mut_mid3.is_valid = false;
-                        // ポイントのリセットはここではしない
-                        // mut_mid3.point = 0;
-                        if mid1.win_flag {
-                            team_point += mid_point;
-                        } else {
-                            opponent_team_point += mid_point;
-                        }
-                    } else if mid3.is_valid {
-                        let mut_mid3 = records.get_mut(5).unwrap();
-                        mut_mid3.is_valid = true;
-                        mut_mid3.point = mid_point;
-                        // ポイントのリセットはここではしない
-                        // let mut_mid2 = records.get_mut(4).unwrap();
-                        // mut_mid2.point = 0;
-                        if mid3.win_flag {
-                            team_point += mid_point;
-                        } else {
-                            opponent_team_point += mid_point;
-                        }
-                    }
-                }
-
-                // 大将戦
-                let general_point = GENERAL.get_point();
-                if general1.is_valid && general2.is_valid && general3.is_valid {
-                    if general1.win_flag == general2.win_flag
-                        && general1.win_flag == general3.win_flag
-                    {
-                        let mut_general3 = records.get_mut(8).unwrap();
-                        mut_general3.point = general_point;
-                        let mut_general4 = records.get_mut(9).unwrap();
-                        mut_general4.is_valid = false;
-                        // ポイントのリセットはここではしない
-                        // mut_general4.point = 0;
-                        let mut_general5 = records.get_mut(10).unwrap();
-                        mut_general5.is_valid = false;
-                        // ポイントのリセットはここではしない
-                        // mut_general5.point = 0;
-                        if general1.win_flag {
-                            team_point += general_point;
-                        } else {
-                            opponent_team_point += general_point;
-                        }
-                    } else if general4.is_valid {
-                        let decide_flag = (general4.win_flag == general1.win_flag
-                            && general4.win_flag == general2.win_flag
-                            && general4.win_flag != general3.win_flag)
-                            || (general4.win_flag == general1.win_flag
-                                && general4.win_flag != general2.win_flag
-                                && general4.win_flag == general3.win_flag)
-                            || (general4.win_flag != general1.win_flag
-                                && general4.win_flag == general2.win_flag
-                                && general4.win_flag == general3.win_flag);
-                        if decide_flag {
-                            // ポイントのリセットはここではしない
-                            let mut_general5 = records.get_mut(10).unwrap();
-                            mut_general5.is_valid = false;
-                            let mut_general4 = records.get_mut(9).unwrap();
-                            mut_general4.is_valid = true;
-                            mut_general4.point = general_point;
-                            // mut_general5.point = 0;
-                            if mut_general4.win_flag {
-                                team_point += general_point;
-                            } else {
-                                opponent_team_point += general_point;
-                            }
-                        } else {
-                            if general5.is_valid {
-                                let mut_general5 = records.get_mut(10).unwrap();
-                                mut_general5.is_valid = true;
-                                mut_general5.point = general_point;
-                                // ポイントのリセットはここではしない
-                                // let mut_general4 = records.get_mut(9).unwrap();
-                                // mut_general4.point = 0;
-                                if mut_general5.win_flag {
-                                    team_point += general_point;
-                                } else {
-                                    opponent_team_point += general_point;
-                                }
-                            }
-                        }
-                    }
-                }
-
-                // 延長戦
-                let mut_extra1 = records.get_mut(11).unwrap();
-                mut_extra1.is_valid = if team_point == van_point + mid_point
-                    && opponent_team_point == general_point
-                {
-                    mut_extra1.point = EXTRA.get_point();
-                    true
-                } else {
-                    false
-                };
-            }
-            _ => {}
+            JP2024DivisionS | JP2024DivisionF | JP2024GrandFinal => vec![],
+            JP2024Playoff => vec![JP2024DivisionS, JP2024DivisionF],
         }
     }
+    // 組み合わせが参照するシードの数
+    pub fn get_seed_count(&self) -> usize {
+        self.get_bracket()
+            .iter()
+            .flat_map(|bracket_match| [&bracket_match.slot, &bracket_match.opponent_slot])
+            .filter_map(|slot| match slot {
+                SflSlot::Seed(n) => Some(n + 1),
+                SflSlot::Winner(_, _) => None,
+            })
+            .max()
+            .unwrap_or(0)
+    }
+    // 前のステージの順位表からシード順を決める
+    pub fn get_knockout_seeds(&self, previous_standings: &[Vec<SflTeam>]) -> Vec<SflTeam> {
+        let advance_count = self.get_advance_count();
+        previous_standings
+            .iter()
+            .flat_map(|standings| standings.iter().take(advance_count).cloned())
+            .collect()
+    }
+    pub fn match_to_records(&self, sfl_match: &SflMatch) -> Vec<SflRecord> {
+        sfl_match
+            .sfl_stage
+            .get_match_format()
+            .get_games()
+            .iter()
+            .map(|(set_number, game_type)| {
+                SflRecord {
+                    sfl_match: sfl_match.to_owned(),
+                    set_number: *set_number,
+                    win_flag: false,
+                    game_type: game_type.to_owned(),
+                    // pointはcorrect_recordでセットする
+                    point: 0,
+                    is_valid: false,
+                    is_prediction: true,
+                    players: None,
+                }
+            })
+            .collect()
+    }
+    // 試合のルールに従って決着を判定する（SflMatchFormat::correct_records）
+    pub fn correct_records(&self, records: &mut [SflRecord]) {
+        self.get_match_format().correct_records(records);
+    }
+    // ノックアウト方式の試合の勝者
+    pub fn get_knockout_winner(&self, records: &[SflRecord]) -> Option<bool> {
+        self.get_match_format().get_winner(records)
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -431,53 +496,210 @@ pub struct SflMatch {
     pub sfl_stage: SflStage,
     pub team: SflTeam,
     pub opponent_team: SflTeam,
-    is_home: bool,
+    // team がホームなら true
+    pub is_home: bool,
+}
+
+// トーナメントの枠。シード順の番号か、(節, 節内の順序) の試合の勝者
+#[derive(Clone, Debug)]
+pub enum SflSlot {
+    Seed(usize),
+    Winner(u32, u32),
 }
 
-#[derive(Clone, Debug, Hash, Eq, PartialEq)]
-pub enum SflTeam {
-    G8S,
-    DFM,
-    SOL,
-    IBS,
-    OJA,
-    SNB,
-    CR,
-    CAG,
-    IXA,
-    RC,
-    VAR,
-    FAV,
+#[derive(Clone, Debug)]
+pub struct SflBracketMatch {
+    pub section: u32,
+    pub branch: u32,
+    // 上位シード側。ホームになる
+    pub slot: SflSlot,
+    pub opponent_slot: SflSlot,
 }
 
+// ディビジョン単位のチーム、対戦カード、すでに行われた結果
+// シーズン定義ファイルから読み込む（season.rs）
+#[derive(Clone, Debug)]
+pub struct SflDivision {
+    pub sfl_stage: SflStage,
+    // 並び順は同ポイント・同得失時の順位決定に使う
+    pub teams: Vec<SflTeam>,
+    pub matches: Vec<SflMatch>,
+    // matches と同じ並び。未実施の試合は空
+    pub played_match_results: Vec<Vec<bool>>,
+    // matches と同じ並びで、セットごとの選手。未実施のセットに入っていれば出場予定として予想に使う
+    pub match_players: Vec<Vec<Option<SflSetPlayers>>>,
+    // チームの登録選手。ないチームは match_players に出てくる選手をメンバーとする
+    pub rosters: HashMap<SflTeam, Vec<String>>,
+}
+
+impl SflDivision {
+    pub fn get_initial_records(&self) -> Vec<Vec<SflRecord>> {
+        self.matches
+            .iter()
+            .zip(self.match_players.iter())
+            .map(|(sfl_match, match_players)| {
+                let mut records = self.sfl_stage.match_to_records(sfl_match);
+                for (record, players) in records.iter_mut().zip(match_players.iter()) {
+                    record.players = players.to_owned();
+                }
+                records
+            })
+            .collect()
+    }
+    // チームのメンバー。rosters になければ match_players に出てきた順
+    pub fn get_team_members(&self, team: &SflTeam) -> Vec<String> {
+        if let Some(roster) = self.rosters.get(team) {
+            return roster.to_owned();
+        }
+        let mut members: Vec<String> = vec![];
+        for (sfl_match, match_players) in self.matches.iter().zip(self.match_players.iter()) {
+            for players in match_players.iter().flatten() {
+                let player = if sfl_match.team == *team {
+                    &players.player
+                } else if sfl_match.opponent_team == *team {
+                    &players.opponent_player
+                } else {
+                    continue;
+                };
+                if !members.contains(player) {
+                    members.push(player.to_owned());
+                }
+            }
+        }
+        members
+    }
+    pub fn get_team_order(&self, team: &SflTeam) -> usize {
+        self.teams.iter().position(|t| t == team).unwrap()
+    }
+    // すでに行われた結果を反映したレコード。勝ち抜けが決まった後のセットは is_valid = false になる
+    pub fn get_played_records(&self, index: usize) -> Vec<SflRecord> {
+        let mut records = self.sfl_stage.match_to_records(&self.matches[index]);
+        for (record, win_flag) in records
+            .iter_mut()
+            .zip(self.played_match_results[index].iter())
+        {
+            record.win_flag = *win_flag;
+            record.is_valid = true;
+        }
+        self.sfl_stage.correct_records(&mut records);
+        records
+    }
+    // すでに行われた結果だけで (team のポイント, opponent_team のポイント) を集計する
+    pub fn get_played_points(&self, index: usize) -> (u32, u32) {
+        let records = self.get_played_records(index);
+        records.iter().filter(|r| r.is_valid).fold(
+            (0, 0),
+            |(team_point, opponent_team_point), r| {
+                if r.win_flag {
+                    (team_point + r.point, opponent_team_point)
+                } else {
+                    (team_point, opponent_team_point + r.point)
+                }
+            },
+        )
+    }
+}
+
+// チームは略称（G8S, DFM など）で識別する
+#[derive(Clone, Debug, Hash, Eq, PartialEq, Deserialize)]
+pub struct SflTeam(pub String);
+
 impl fmt::Display for SflTeam {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{:?}", self)
+        write!(f, "{}", self.0)
     }
 }
 
+pub type RatingKey = (SflTeam, u8);
+pub type RateKeyFunction = fn(&SflRecord) -> (RatingKey, RatingKey);
+// 順位ごとの回数と (現在ポイント, 予想ポイント合計, 現在得失, 予想得失合計)
+pub type PlaceSimCount = (Vec<u32>, (u32, u64, i32, i64));
+
+#[derive(Clone, Copy, Debug)]
 pub enum SflRatingSetting {
+    // レーティングを計算せず、すべてのセットを五分五分として予想する
+    NoRating,
     TeamOnly,
     HomeAway,
     GameType,
     HomeAwayGameType,
+    // 選手ごとのレート（team_rating.rs）からチームの強さを出す
+    Player,
+}
+
+impl SflRatingSetting {
+    // レーティングのキーと表示名
+    // MM: 先鋒・中堅、LD: 大将・延長、AW: アウェイ、HM: ホーム
+    pub fn get_rating_keys(&self) -> Vec<(u8, &'static str)> {
+        match self {
+            SflRatingSetting::NoRating | SflRatingSetting::TeamOnly | SflRatingSetting::Player => {
+                vec![(000_u8, "TEAM")]
+            }
+            SflRatingSetting::HomeAway => vec![(120_u8, "AW"), (121_u8, "HM")],
+            SflRatingSetting::GameType => vec![(102_u8, "MM"), (112_u8, "LD")],
+            SflRatingSetting::HomeAwayGameType => vec![
+                (100_u8, "MMAW"),
+                (101_u8, "MMHM"),
+                (110_u8, "LDAW"),
+                (111_u8, "LDHM"),
+            ],
+        }
+    }
+    pub fn is_rating_enabled(&self) -> bool {
+        !matches!(self, SflRatingSetting::NoRating)
+    }
+    pub fn is_player_rating(&self) -> bool {
+        matches!(self, SflRatingSetting::Player)
+    }
+    // 予想設定のチェックボックス（レートを使う、ホーム・アウェイを区別する、戦の種類を区別する）との対応
+    pub fn from_flags(is_rating: bool, is_home_away: bool, is_game_type: bool) -> SflRatingSetting {
+        match (is_rating, is_home_away, is_game_type) {
+            (false, _, _) => SflRatingSetting::NoRating,
+            (true, false, false) => SflRatingSetting::TeamOnly,
+            (true, true, false) => SflRatingSetting::HomeAway,
+            (true, false, true) => SflRatingSetting::GameType,
+            (true, true, true) => SflRatingSetting::HomeAwayGameType,
+        }
+    }
+    // Player はチェックボックスで選べないので TeamOnly と同じ表示にする
+    pub fn to_flags(&self) -> (bool, bool, bool) {
+        match self {
+            SflRatingSetting::NoRating => (false, false, false),
+            SflRatingSetting::TeamOnly | SflRatingSetting::Player => (true, false, false),
+            SflRatingSetting::HomeAway => (true, true, false),
+            SflRatingSetting::GameType => (true, false, true),
+            SflRatingSetting::HomeAwayGameType => (true, true, true),
+        }
+    }
+}
+
+impl FromStr for SflRatingSetting {
+    type Err = String;
+    fn from_str(s: &str) -> Result<SflRatingSetting, String> {
+        match s {
+            "NoRating" => Ok(SflRatingSetting::NoRating),
+            "TeamOnly" => Ok(SflRatingSetting::TeamOnly),
+            "HomeAway" => Ok(SflRatingSetting::HomeAway),
+            "GameType" => Ok(SflRatingSetting::GameType),
+            "HomeAwayGameType" => Ok(SflRatingSetting::HomeAwayGameType),
+            "Player" => Ok(SflRatingSetting::Player),
+            _ => Err(format!("不明なレーティング設定: {}", s)),
+        }
+    }
 }
 
 pub fn create_key_function_and_init_rating_map(
     setting: SflRatingSetting,
     teams: Vec<SflTeam>,
-) -> (
-    fn(&SflRecord) -> ((SflTeam, u8), (SflTeam, u8)),
-    HashMap<(SflTeam, u8), f64>,
-) {
+) -> (RateKeyFunction, HashMap<RatingKey, f64>) {
     let default_rating = 1500_f64;
-    let mut rating_map: HashMap<(SflTeam, u8), f64> = HashMap::new();
+    let mut rating_map: HashMap<RatingKey, f64> = HashMap::new();
     match setting {
-        SflRatingSetting::TeamOnly => {
+        SflRatingSetting::NoRating | SflRatingSetting::TeamOnly | SflRatingSetting::Player => {
             for team in teams.iter() {
                 rating_map.insert((team.to_owned(), 000_u8), default_rating);
             }
-            fn team_only_function(record: &SflRecord) -> ((SflTeam, u8), (SflTeam, u8)) {
+            fn team_only_function(record: &SflRecord) -> (RatingKey, RatingKey) {
                 (
                     (record.sfl_match.team.to_owned(), 000_u8),
                     (record.sfl_match.opponent_team.to_owned(), 000_u8),
@@ -490,7 +712,7 @@ pub fn create_key_function_and_init_rating_map(
                 rating_map.insert((team.to_owned(), 120_u8), default_rating);
                 rating_map.insert((team.to_owned(), 121_u8), default_rating);
             }
-            fn home_away_function(record: &SflRecord) -> ((SflTeam, u8), (SflTeam, u8)) {
+            fn home_away_function(record: &SflRecord) -> (RatingKey, RatingKey) {
                 if record.sfl_match.is_home {
                     (
                         (record.sfl_match.team.to_owned(), 121_u8),
@@ -510,7 +732,7 @@ pub fn create_key_function_and_init_rating_map(
                 rating_map.insert((team.to_owned(), 102_u8), default_rating);
                 rating_map.insert((team.to_owned(), 112_u8), default_rating);
             }
-            fn game_type_function(record: &SflRecord) -> ((SflTeam, u8), (SflTeam, u8)) {
+            fn game_type_function(record: &SflRecord) -> (RatingKey, RatingKey) {
                 if record.game_type.is_leader() {
                     (
                         (record.sfl_match.team.to_owned(), 112_u8),
@@ -531,7 +753,7 @@ pub fn create_key_function_and_init_rating_map(
                     rating_map.insert((team.to_owned(), n), default_rating);
                 }
             }
-            fn home_away_game_type_function(record: &SflRecord) -> ((SflTeam, u8), (SflTeam, u8)) {
+            fn home_away_game_type_function(record: &SflRecord) -> (RatingKey, RatingKey) {
                 if record.sfl_match.is_home {
                     if record.game_type.is_leader() {
                         (
@@ -563,12 +785,176 @@ pub fn create_key_function_and_init_rating_map(
     }
 }
 
-pub fn get_place_sim_count(
-    sfl_stage: SflStage,
-) -> HashMap<SflTeam, (Vec<u32>, (u32, u32, i32, i32))> {
-    let mut count: HashMap<SflTeam, (Vec<u32>, (u32, u32, i32, i32))> = HashMap::new();
-    for team in sfl_stage.get_teams().into_iter() {
-        count.insert(team, (vec![0; 6], (0, 0, 0, 0)));
+pub fn get_place_sim_count(teams: &[SflTeam]) -> HashMap<SflTeam, PlaceSimCount> {
+    let mut count: HashMap<SflTeam, PlaceSimCount> = HashMap::new();
+    for team in teams.iter().cloned() {
+        count.insert(team, (vec![0; teams.len()], (0, 0, 0, 0)));
     }
     count
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const W: bool = true;
+    const L: bool = false;
+
+    // results の分のセットを行われたものとして、team 側から見た勝敗を入れる
+    fn create_records(sfl_stage: SflStage, results: &[bool]) -> Vec<SflRecord> {
+        let sfl_match = SflMatch {
+            section: 1,
+            branch: 1,
+            sfl_stage,
+            team: SflTeam("A".to_string()),
+            opponent_team: SflTeam("B".to_string()),
+            is_home: true,
+        };
+        let mut records = sfl_stage.match_to_records(&sfl_match);
+        for (record, win_flag) in records.iter_mut().zip(results.iter()) {
+            record.win_flag = *win_flag;
+            record.is_valid = true;
+        }
+        records
+    }
+
+    fn correct(sfl_stage: SflStage, results: &[bool]) -> Vec<SflRecord> {
+        let mut records = create_records(sfl_stage, results);
+        sfl_stage.correct_records(&mut records);
+        records
+    }
+
+    // 決着したセットの (位置, team 側の勝ち, ポイント)
+    fn get_decided(records: &[SflRecord]) -> Vec<(usize, bool, u32)> {
+        records
+            .iter()
+            .enumerate()
+            .filter(|(_, r)| r.is_valid && r.point != 0)
+            .map(|(index, r)| (index, r.win_flag, r.point))
+            .collect()
+    }
+
+    fn get_valid_indices(records: &[SflRecord]) -> Vec<usize> {
+        records
+            .iter()
+            .enumerate()
+            .filter(|(_, r)| r.is_valid)
+            .map(|(index, _)| index)
+            .collect()
+    }
+
+    // ディビジョンのセットの位置: 先鋒 0..3, 中堅 3..6, 大将 6..11, 延長 11
+
+    #[test]
+    fn division_straight_wins() {
+        let records = correct(JP2024DivisionS, &[W, W, W, W, W, W, W, W, W, W, W, W]);
+        assert_eq!(
+            get_decided(&records),
+            vec![(1, W, 10), (4, W, 10), (8, W, 20)]
+        );
+        assert_eq!(get_valid_indices(&records), vec![0, 1, 3, 4, 6, 7, 8]);
+    }
+
+    #[test]
+    fn division_bouts_go_the_distance() {
+        let records = correct(JP2024DivisionS, &[W, L, W, L, W, L, L, W, W, L, L, W]);
+        assert_eq!(
+            get_decided(&records),
+            vec![(2, W, 10), (5, L, 10), (10, L, 20)]
+        );
+        // 10-30 なので延長戦はない
+        assert!(!records[11].is_valid);
+    }
+
+    #[test]
+    fn division_tie_plays_extra_when_team_wins_van_and_mid() {
+        let records = correct(JP2024DivisionS, &[W, W, W, W, W, W, L, L, L, L, L, L]);
+        assert_eq!(
+            get_decided(&records),
+            vec![(1, W, 10), (4, W, 10), (8, L, 20), (11, L, 5)]
+        );
+    }
+
+    #[test]
+    fn division_tie_plays_extra_when_opponent_team_wins_van_and_mid() {
+        let records = correct(JP2024DivisionS, &[L, L, L, L, L, L, W, W, W, W, W, W]);
+        assert_eq!(
+            get_decided(&records),
+            vec![(1, L, 10), (4, L, 10), (8, W, 20), (11, W, 5)]
+        );
+    }
+
+    #[test]
+    fn division_no_extra_without_tie() {
+        let records = correct(JP2024DivisionS, &[W, W, W, L, L, L, W, W, W, W, W, W]);
+        assert_eq!(
+            get_decided(&records),
+            vec![(1, W, 10), (4, L, 10), (8, W, 20)]
+        );
+        assert!(!records[11].is_valid);
+    }
+
+    #[test]
+    fn division_partly_played() {
+        // 先鋒戦は 2-0 で決着、中堅戦は 1本目まで
+        let results = [W, W, W, L];
+        let mut records = create_records(JP2024DivisionS, &results);
+        // 先鋒戦の3本目は行われていない
+        records[2].is_valid = false;
+        JP2024DivisionS.correct_records(&mut records);
+        assert_eq!(get_decided(&records), vec![(1, W, 10)]);
+        assert_eq!(get_valid_indices(&records), vec![0, 1, 3]);
+    }
+
+    #[test]
+    fn playoff_finishes_after_two_bouts() {
+        let records = correct(JP2024Playoff, &[W, W, W, W, W, W, W, W, W, W, W]);
+        assert_eq!(get_decided(&records), vec![(1, W, 10), (4, W, 10)]);
+        // 大将戦は行わない
+        assert!(records[6..].iter().all(|r| !r.is_valid));
+        assert_eq!(JP2024Playoff.get_knockout_winner(&records), Some(W));
+    }
+
+    #[test]
+    fn playoff_decided_by_general() {
+        let records = correct(JP2024Playoff, &[W, W, W, L, W, L, L, W, W, L, L]);
+        assert_eq!(
+            get_decided(&records),
+            vec![(1, W, 10), (5, L, 10), (10, L, 20)]
+        );
+        assert_eq!(JP2024Playoff.get_knockout_winner(&records), Some(L));
+    }
+
+    #[test]
+    fn playoff_undecided_while_partly_played() {
+        let records = correct(JP2024Playoff, &[W, W, W, L]);
+        assert_eq!(JP2024Playoff.get_knockout_winner(&records), None);
+    }
+
+    #[test]
+    fn point_range() {
+        assert_eq!(
+            JP2024DivisionS.get_match_format().get_point_range(),
+            40..=45
+        );
+        assert_eq!(JP2024Playoff.get_match_format().get_point_range(), 20..=40);
+        assert_eq!(
+            JP2024GrandFinal.get_match_format().get_point_range(),
+            20..=40
+        );
+    }
+
+    #[test]
+    fn set_players_character_is_optional() {
+        let with_characters: SflSetPlayers = serde_json::from_str(
+            r#"{ "player": "A", "character": "ケン", "opponent_player": "B", "opponent_character": "JP" }"#,
+        )
+        .unwrap();
+        assert_eq!(with_characters.character.as_deref(), Some("ケン"));
+        assert_eq!(with_characters.opponent_character.as_deref(), Some("JP"));
+        let without_characters: SflSetPlayers =
+            serde_json::from_str(r#"{ "player": "A", "opponent_player": "B" }"#).unwrap();
+        assert_eq!(without_characters.character, None);
+        assert_eq!(without_characters.opponent_player, "B");
+    }
+}
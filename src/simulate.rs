@@ -0,0 +1,700 @@
+use crate::elo::{get_win_percentage, update_rating, DEFAULT_K};
+use crate::season::SflSeason;
+use crate::sfl::{
+    create_key_function_and_init_rating_map, get_place_sim_count, RateKeyFunction, RatingKey,
+    SflDivision, SflMatch, SflRatingSetting, SflRecord, SflSlot, SflStage, SflTeam,
+};
+use crate::team_rating::{SflPlayerRating, TrueSkillSetting};
+use rand::prelude::*;
+use std::collections::HashMap;
+use std::thread;
+
+// ディビジョンのシミュレーション結果（チームごと）
+#[derive(Clone, Debug)]
+pub struct SimulationResult {
+    pub team: SflTeam,
+    // 実施済みの試合だけのポイントとバトル得失
+    pub current_point: u32,
+    pub current_battle: i32,
+    // 残り試合を含めたシーズン終了時のポイントとバトル得失の期待値
+    // 試行回数が0なら現在の値
+    pub expected_point: f64,
+    pub expected_battle: f64,
+    // 1位から順に、その順位になった回数と確率
+    pub place_counts: Vec<u32>,
+    pub place_probabilities: Vec<f64>,
+    // SflRatingSetting::get_rating_keys の表示名とレーティング
+    pub ratings: Vec<(&'static str, f64)>,
+}
+
+// シーズンを通したシミュレーション結果
+#[derive(Clone, Debug)]
+pub struct SeasonSimulationResult {
+    // ラウンドの表示名。最後は優勝
+    pub round_names: Vec<String>,
+    // チームごとに、round_names の順でそのラウンドまで進んだ確率
+    pub round_probabilities: Vec<(SflTeam, Vec<f64>)>,
+}
+
+// 試行はこの回数ごとのまとまりに分けて、まとまりごとに乱数を作る
+const TRIAL_CHUNK_SIZE: u32 = 1000;
+
+#[derive(Clone, Copy, Debug)]
+pub struct SimulateSetting {
+    pub trial_count: u32,
+    pub seed: u64,
+    pub sfl_rate_setting: SflRatingSetting,
+    // 試行を並列に実行するスレッド数。結果はスレッド数によらない
+    pub thread_count: usize,
+}
+
+impl Default for SimulateSetting {
+    fn default() -> SimulateSetting {
+        SimulateSetting {
+            trial_count: 10000,
+            seed: 5,
+            sfl_rate_setting: SflRatingSetting::HomeAwayGameType,
+            thread_count: thread::available_parallelism().map_or(1, |n| n.get()),
+        }
+    }
+}
+
+// 試行を TRIAL_CHUNK_SIZE ごとのまとまりに分けてスレッドで並列に実行し、集計を合算する
+// まとまりごとの乱数は seed から順に作るので、スレッド数や実行順によらず同じ集計になる
+// state はスレッドごとに複製して試行のたびに使い回す
+fn run_trials<S, C>(
+    setting: &SimulateSetting,
+    state: &S,
+    count: &C,
+    run_trial: impl Fn(&mut S, &mut StdRng, &mut C) + Sync,
+    merge_count: impl Fn(&mut C, C),
+) -> C
+where
+    S: Clone + Send + Sync,
+    C: Clone + Send + Sync,
+{
+    let chunk_count = setting.trial_count.div_ceil(TRIAL_CHUNK_SIZE);
+    let mut seed_rng = StdRng::seed_from_u64(setting.seed);
+    let chunk_seeds: Vec<u64> = (0..chunk_count).map(|_| seed_rng.next_u64()).collect();
+    let thread_count = setting.thread_count.clamp(1, chunk_count.max(1) as usize);
+    let thread_counts: Vec<C> = thread::scope(|scope| {
+        let handles: Vec<_> = (0..thread_count)
+            .map(|thread_index| {
+                let chunk_seeds = &chunk_seeds;
+                let run_trial = &run_trial;
+                let mut state = state.clone();
+                let mut count = count.clone();
+                scope.spawn(move || {
+                    for chunk in (thread_index..chunk_seeds.len()).step_by(thread_count) {
+                        let mut rng = StdRng::seed_from_u64(chunk_seeds[chunk]);
+                        let chunk_start = chunk as u32 * TRIAL_CHUNK_SIZE;
+                        let chunk_end = setting.trial_count.min(chunk_start + TRIAL_CHUNK_SIZE);
+                        for _ in chunk_start..chunk_end {
+                            run_trial(&mut state, &mut rng, &mut count);
+                        }
+                    }
+                    count
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .map(|handle| handle.join().unwrap())
+            .collect()
+    });
+    let mut merged_count = count.clone();
+    for thread_count in thread_counts.into_iter() {
+        merge_count(&mut merged_count, thread_count);
+    }
+    merged_count
+}
+
+// 実施済みの結果を反映したディビジョンの状態
+// 試行のたびに予想のレコードを上書きして使い回す
+#[derive(Clone)]
+pub struct SflDivisionState {
+    pub rate_key_function: RateKeyFunction,
+    pub rating_map: HashMap<RatingKey, f64>,
+    // SflRatingSetting::Player のときだけ。rating_map の代わりに予想に使う
+    pub player_rating: Option<SflPlayerRating>,
+    pub record_matches: Vec<Vec<SflRecord>>,
+}
+
+pub fn get_division_state(
+    division: &SflDivision,
+    sfl_rate_setting: SflRatingSetting,
+) -> SflDivisionState {
+    let sfl_stage = division.sfl_stage;
+    let (rate_key_function, mut rating_map) =
+        create_key_function_and_init_rating_map(sfl_rate_setting, division.teams.clone());
+    let mut player_rating = if sfl_rate_setting.is_player_rating() {
+        Some(SflPlayerRating::new(TrueSkillSetting::default(), division))
+    } else {
+        None
+    };
+    // ステージに応じた初期状態のレコードを生成
+    let mut initial_record_matches: Vec<Vec<SflRecord>> = division.get_initial_records();
+
+    // すでに行われた結果を初期状態のレコードに記入
+    for (index, played_match_result) in division.played_match_results.iter().enumerate() {
+        // すでに行われたマッチ結果のインデックスの方がマッチ予定より大きい時はbreak
+        let initial_records = initial_record_matches.get_mut(index);
+        if initial_records.is_none() {
+            break;
+        }
+        let initial_records = initial_records.unwrap();
+        for (index, win_flag) in played_match_result.iter().enumerate() {
+            // すでに行われたバトル結果のインデックスの方が初期レコードのサイズより大きい時はpanic
+            let initial_record = initial_records.get_mut(index);
+            if initial_record.is_none() {
+                break;
+            }
+            let initial_record = initial_record.unwrap();
+            initial_record.win_flag = *win_flag; // 初期状態は false
+            initial_record.is_valid = true; // 初期状態は false
+            initial_record.is_prediction = false; // 初期状態は true
+        }
+    }
+
+    // すでに行われた分の補正を行い、その後にレーティングに反映する
+    for records in initial_record_matches.iter_mut() {
+        // 第1セットから予想の場合は補正対象外
+        if records.first().unwrap().is_prediction {
+            continue;
+        }
+        // 補正実行
+        // すでに行われた分を補正して、実際には行われなかったセットに is_valid = false を立てる
+        // 決着局にポイントを付与する
+        sfl_stage.correct_records(records);
+        if !sfl_rate_setting.is_rating_enabled() {
+            continue;
+        }
+
+        // レーティング反映開始
+        for record in records.iter_mut() {
+            // 無効なセットおよび予想のセットは無視
+            // ただし1マッチ最大12セットのうち、途中のセットが無効になることはあるので、breakはしない
+            if !record.is_valid || record.is_prediction {
+                continue;
+            }
+            if let Some(ref mut player_rating) = player_rating {
+                player_rating.update(record);
+                continue;
+            }
+            let (team_key, opponent_team_key) = rate_key_function(record);
+            let team_rating = rating_map.get(&team_key).unwrap();
+            let opponent_team_rating = rating_map.get(&opponent_team_key).unwrap();
+            let (updated_rating, updated_opponent_rating) = update_rating(
+                team_rating,
+                opponent_team_rating,
+                &record.win_flag,
+                &(DEFAULT_K, DEFAULT_K),
+            );
+            rating_map.insert(team_key, updated_rating);
+            rating_map.insert(opponent_team_key, updated_opponent_rating);
+        }
+    }
+    SflDivisionState {
+        rate_key_function,
+        rating_map,
+        player_rating,
+        record_matches: initial_record_matches,
+    }
+}
+
+// 実施済みの結果だけでチームごとの (現在ポイント, 現在バトル得失) を集計する
+pub fn get_current_points(
+    division: &SflDivision,
+    state: &SflDivisionState,
+) -> HashMap<SflTeam, (u32, i32)> {
+    let mut current_points: HashMap<SflTeam, (u32, i32)> = HashMap::new();
+    for team in division.teams.iter() {
+        // チームが含まれる有効なレコードのみ抽出
+        let records: Vec<&SflRecord> = state
+            .record_matches
+            .iter()
+            .flatten()
+            .filter(|r| {
+                r.is_valid
+                    && !r.is_prediction
+                    && ((r.sfl_match.team == *team) || (r.sfl_match.opponent_team == *team))
+            })
+            .collect();
+        // 現在ポイントを集計
+        let point: u32 = records
+            .iter()
+            .filter(|r| {
+                r.point != 0
+                    && ((r.win_flag && r.sfl_match.team == *team)
+                        || (!r.win_flag && r.sfl_match.opponent_team == *team))
+            })
+            .map(|r| r.point)
+            .sum();
+        // 現在バトル得失を集計
+        let battle: i32 = records
+            .iter()
+            .map(|r| {
+                if (r.win_flag && r.sfl_match.team == *team)
+                    || (!r.win_flag && r.sfl_match.opponent_team == *team)
+                {
+                    1
+                } else {
+                    -1
+                }
+            })
+            .sum();
+        current_points.insert(team.to_owned(), (point, battle));
+    }
+    current_points
+}
+
+// 残り試合を1回分シミュレーションして、(チーム, ポイント, バトル得失) を順位順に返す
+pub fn simulate_division(
+    division: &SflDivision,
+    state: &mut SflDivisionState,
+    rng: &mut StdRng,
+) -> Vec<(SflTeam, u32, i32)> {
+    let sfl_stage = division.sfl_stage;
+    let match_format = sfl_stage.get_match_format();
+    let point_range = match_format.get_point_range();
+    // ランダムに結果をセット（レーティング処理を追加するならここ）
+    for records in state.record_matches.iter_mut() {
+        for record in records.iter_mut() {
+            // 前の試行でポイントが入っているのでリセットする
+            record.point = 0;
+            // すでに行われた結果では is_prediction: false となっているので continue
+            if !record.is_prediction {
+                continue;
+            }
+            predict_record(
+                record,
+                state.rate_key_function,
+                &state.rating_map,
+                state.player_rating.as_ref(),
+                rng,
+            );
+        }
+
+        // 予想分の補正処理
+        match_format.correct_records(records);
+        let sum: u32 = records.iter().map(|r| r.point).sum();
+        // ポイントのセットがうまくいっていないと1試合のポイントが範囲外になる
+        if !point_range.contains(&sum) {
+            panic!(
+                "1試合のポイント {} が {:?} の範囲外: {:?}",
+                sum, point_range, records
+            );
+        }
+    }
+
+    // 一次元vectorに変更
+    let sfl_records: Vec<&SflRecord> = state.record_matches.iter().flatten().collect();
+
+    // この試行におけるポイント、バトル得失を集計するmap
+    let mut point_map: HashMap<SflTeam, (u32, i32)> = HashMap::new();
+    // チームの分だけ初期化
+    for team in division.teams.iter().cloned() {
+        point_map.insert(team, (0, 0));
+    }
+
+    // レコードごとにポイント集計開始
+    for record in sfl_records.iter() {
+        // 無効ならスキップ
+        if !record.is_valid {
+            continue;
+        }
+        let team = record.sfl_match.team.to_owned();
+        let opponent_team = record.sfl_match.opponent_team.to_owned();
+        let (mut team_point, mut team_battle) = point_map.get(&team).unwrap();
+        let (mut opponent_team_point, mut opponent_team_battle) =
+            point_map.get(&opponent_team).unwrap();
+        if record.win_flag {
+            team_point += record.point;
+            team_battle += 1;
+            opponent_team_battle -= 1;
+        } else {
+            opponent_team_point += record.point;
+            team_battle -= 1;
+            opponent_team_battle += 1;
+        }
+        point_map.insert(team, (team_point, team_battle));
+        point_map.insert(opponent_team, (opponent_team_point, opponent_team_battle));
+    }
+
+    let mut sortable: Vec<(SflTeam, u32, i32)> = point_map
+        .into_iter()
+        .map(|(team, (point, battle))| (team, point, battle))
+        .collect();
+    sortable.sort_by(|(a_team, a_point, a_battle), (b_team, b_point, b_battle)| {
+        b_point.cmp(a_point).then(b_battle.cmp(a_battle)).then(
+            division
+                .get_team_order(b_team)
+                .cmp(&division.get_team_order(a_team)),
+        )
+    });
+    sortable
+}
+
+// division.teams の順に結果を返す
+pub fn get_simulate_result(
+    division: &SflDivision,
+    setting: &SimulateSetting,
+) -> Vec<SimulationResult> {
+    let sfl_rate_setting = setting.sfl_rate_setting;
+    let state = get_division_state(division, sfl_rate_setting);
+
+    // 順位の集計map
+    let mut place_sim_count = run_trials(
+        setting,
+        &state,
+        &get_place_sim_count(&division.teams),
+        |state, rng, place_sim_count| {
+            let sortable = simulate_division(division, state, rng);
+            for (n, (team, point, battle)) in sortable.iter().enumerate() {
+                let (count, points) = place_sim_count.get_mut(team).unwrap();
+                points.1 += *point as u64;
+                points.3 += *battle as i64;
+                count[n] += 1;
+            }
+        },
+        |place_sim_count, thread_place_sim_count| {
+            for (team, (thread_count, thread_points)) in thread_place_sim_count.into_iter() {
+                let (count, points) = place_sim_count.get_mut(&team).unwrap();
+                for (n, c) in thread_count.into_iter().enumerate() {
+                    count[n] += c;
+                }
+                points.1 += thread_points.1;
+                points.3 += thread_points.3;
+            }
+        },
+    );
+
+    // チームごとに現在ポイントと現在バトル得失を集計
+    for (team, (point, battle)) in get_current_points(division, &state).into_iter() {
+        let (_, points) = place_sim_count.get_mut(&team).unwrap();
+        points.0 = point;
+        points.2 = battle;
+    }
+
+    division
+        .teams
+        .iter()
+        .map(|team| {
+            let (place_counts, points) = place_sim_count.remove(team).unwrap();
+            let ratings: Vec<(&'static str, f64)> = sfl_rate_setting
+                .get_rating_keys()
+                .into_iter()
+                .map(|(n, name)| match state.player_rating {
+                    Some(ref player_rating) => (name, player_rating.get_team_rating(team)),
+                    None => {
+                        let rating = state.rating_map.get(&(team.to_owned(), n)).unwrap();
+                        (name, rating.to_owned())
+                    }
+                })
+                .collect();
+            let (expected_point, expected_battle) = if setting.trial_count == 0 {
+                (points.0 as f64, points.2 as f64)
+            } else {
+                (
+                    points.1 as f64 / setting.trial_count as f64,
+                    points.3 as f64 / setting.trial_count as f64,
+                )
+            };
+            SimulationResult {
+                team: team.to_owned(),
+                current_point: points.0,
+                current_battle: points.2,
+                expected_point,
+                expected_battle,
+                place_probabilities: get_probabilities(&place_counts, setting.trial_count),
+                place_counts,
+                ratings,
+            }
+        })
+        .collect()
+}
+
+// 回数を試行回数で割った確率。試行回数が0ならすべて0
+fn get_probabilities(counts: &[u32], trial_count: u32) -> Vec<f64> {
+    counts
+        .iter()
+        .map(|count| {
+            if trial_count == 0 {
+                0_f64
+            } else {
+                *count as f64 / trial_count as f64
+            }
+        })
+        .collect()
+}
+
+// ディビジョンのリーグ戦からプレイオフ、グランドファイナルまでを1回の試行で通してシミュレーションする
+// ラウンドはノックアウト方式のステージの節ごとと優勝の並び
+// 1回戦がないシードのチームも、それより後の節に出ていれば1回戦まで進んだものとして数える
+// ディビジョンが足りない、または get_seed_stages と違う順番ならエラー
+pub fn get_season_simulate_result(
+    season: &SflSeason,
+    setting: &SimulateSetting,
+) -> Result<SeasonSimulationResult, String> {
+    let division_states: Vec<SflDivisionState> = season
+        .divisions
+        .iter()
+        .map(|division| get_division_state(division, setting.sfl_rate_setting))
+        .collect();
+    if division_states.is_empty() {
+        return Ok(SeasonSimulationResult {
+            round_names: vec![],
+            round_probabilities: vec![],
+        });
+    }
+    // ノックアウト方式のステージではディビジョンのレーティングをまとめて使う
+    let rate_key_function = division_states.first().unwrap().rate_key_function;
+    let mut rating_map: HashMap<RatingKey, f64> = HashMap::new();
+    let mut player_rating: Option<SflPlayerRating> = None;
+    for state in division_states.iter() {
+        rating_map.extend(state.rating_map.clone());
+        if let Some(ref division_player_rating) = state.player_rating {
+            match player_rating {
+                Some(ref mut player_rating) => player_rating.extend(division_player_rating.clone()),
+                None => player_rating = Some(division_player_rating.clone()),
+            }
+        }
+    }
+
+    // ディビジョンの次から最後までのノックアウト方式のステージ
+    let mut knockout_stages: Vec<SflStage> = vec![];
+    let mut next_stage = season
+        .divisions
+        .first()
+        .and_then(|division| division.sfl_stage.get_next_stage());
+    while let Some(sfl_stage) = next_stage {
+        knockout_stages.push(sfl_stage);
+        next_stage = sfl_stage.get_next_stage();
+    }
+    validate_knockout_seeds(season, &knockout_stages)?;
+    let mut rounds: Vec<(SflStage, u32)> = vec![];
+    for sfl_stage in knockout_stages.iter() {
+        let mut sections: Vec<u32> = sfl_stage
+            .get_bracket()
+            .iter()
+            .map(|bracket_match| bracket_match.section)
+            .collect();
+        sections.dedup();
+        for section in sections.into_iter() {
+            rounds.push((*sfl_stage, section));
+        }
+    }
+    let mut round_names: Vec<String> = rounds
+        .iter()
+        .map(|(sfl_stage, section)| sfl_stage.get_section_name(*section))
+        .collect();
+    round_names.push("優勝".to_string());
+
+    let mut round_count: HashMap<SflTeam, Vec<u32>> = HashMap::new();
+    for division in season.divisions.iter() {
+        for team in division.teams.iter() {
+            round_count.insert(team.to_owned(), vec![0; round_names.len()]);
+        }
+    }
+
+    let round_count = run_trials(
+        setting,
+        &division_states,
+        &round_count,
+        |division_states, rng, round_count| {
+            let mut standings: Vec<Vec<SflTeam>> = season
+                .divisions
+                .iter()
+                .zip(division_states.iter_mut())
+                .map(|(division, state)| {
+                    simulate_division(division, state, rng)
+                        .into_iter()
+                        .map(|(team, _, _)| team)
+                        .collect()
+                })
+                .collect();
+            let mut champion: Option<SflTeam> = None;
+            for sfl_stage in knockout_stages.iter() {
+                let seeds = sfl_stage.get_knockout_seeds(&standings);
+                let results = simulate_knockout(
+                    *sfl_stage,
+                    &seeds,
+                    rate_key_function,
+                    &rating_map,
+                    player_rating.as_ref(),
+                    rng,
+                );
+                for (index, (round_stage, section)) in rounds.iter().enumerate() {
+                    if round_stage != sfl_stage {
+                        continue;
+                    }
+                    for team in seeds.iter() {
+                        let is_reached = results.iter().any(|(sfl_match, _)| {
+                            sfl_match.section >= *section
+                                && (sfl_match.team == *team || sfl_match.opponent_team == *team)
+                        });
+                        if is_reached {
+                            round_count.get_mut(team).unwrap()[index] += 1;
+                        }
+                    }
+                }
+                // 最後の節の勝者が次のステージのシードになる
+                let last_section = results.iter().map(|(m, _)| m.section).max().unwrap();
+                standings = results
+                    .into_iter()
+                    .filter(|(sfl_match, _)| sfl_match.section == last_section)
+                    .map(|(_, winner)| vec![winner])
+                    .collect();
+                champion = standings.first().and_then(|s| s.first()).cloned();
+            }
+            if let Some(champion) = champion {
+                round_count.get_mut(&champion).unwrap()[rounds.len()] += 1;
+            }
+        },
+        |round_count, thread_round_count| {
+            for (team, thread_count) in thread_round_count.into_iter() {
+                let count = round_count.get_mut(&team).unwrap();
+                for (n, c) in thread_count.into_iter().enumerate() {
+                    count[n] += c;
+                }
+            }
+        },
+    );
+    let round_probabilities: Vec<(SflTeam, Vec<f64>)> = season
+        .divisions
+        .iter()
+        .flat_map(|division| division.teams.iter())
+        .map(|team| {
+            let counts = round_count.get(team).unwrap();
+            (
+                team.to_owned(),
+                get_probabilities(counts, setting.trial_count),
+            )
+        })
+        .collect();
+    Ok(SeasonSimulationResult {
+        round_names,
+        round_probabilities,
+    })
+}
+
+// ノックアウト方式の各ステージに組み合わせの分のシードが揃うか確かめる
+fn validate_knockout_seeds(season: &SflSeason, knockout_stages: &[SflStage]) -> Result<(), String> {
+    let Some(first_stage) = knockout_stages.first() else {
+        return Ok(());
+    };
+    let seed_stages = first_stage.get_seed_stages();
+    let division_stages: Vec<SflStage> = season
+        .divisions
+        .iter()
+        .map(|division| division.sfl_stage)
+        .collect();
+    if division_stages != seed_stages {
+        return Err(format!(
+            "{:?} のシードを決めるにはディビジョンが {:?} の順に必要（シーズンファイルは {:?}）",
+            first_stage, seed_stages, division_stages
+        ));
+    }
+    let advance_count = first_stage.get_advance_count();
+    for division in season.divisions.iter() {
+        if division.teams.len() < advance_count {
+            return Err(format!(
+                "{:?}: {:?} に進む {} チームに足りない",
+                division.sfl_stage, first_stage, advance_count
+            ));
+        }
+    }
+    // 前のステージから進むチーム数（ノックアウト方式なら最後の節の試合数）
+    let mut seed_count = season.divisions.len() * advance_count;
+    for sfl_stage in knockout_stages.iter() {
+        if seed_count < sfl_stage.get_seed_count() {
+            return Err(format!(
+                "{:?}: シードが {} チームしかない（{} チーム必要）",
+                sfl_stage,
+                seed_count,
+                sfl_stage.get_seed_count()
+            ));
+        }
+        let bracket = sfl_stage.get_bracket();
+        let last_section = bracket.iter().map(|m| m.section).max().unwrap_or(0);
+        seed_count = bracket.iter().filter(|m| m.section == last_section).count()
+            * sfl_stage
+                .get_next_stage()
+                .map_or(1, |next_stage| next_stage.get_advance_count());
+    }
+    Ok(())
+}
+
+// レーティングから勝率を求めてセットの結果をランダムに決める
+// player_rating があれば選手のレートから勝率を求める
+fn predict_record(
+    record: &mut SflRecord,
+    rate_key_function: RateKeyFunction,
+    rating_map: &HashMap<RatingKey, f64>,
+    player_rating: Option<&SflPlayerRating>,
+    rng: &mut StdRng,
+) {
+    let team_win_percentage = match player_rating {
+        Some(player_rating) => player_rating.get_win_percentage(record),
+        None => {
+            let (ref team_key, ref opponent_team_key) = rate_key_function(record);
+            let team_rating = rating_map.get(team_key).unwrap();
+            let opponent_team_rating = rating_map.get(opponent_team_key).unwrap();
+            get_win_percentage(*team_rating, *opponent_team_rating).0
+        }
+    };
+    // record.win_flag = rng.random();
+    record.win_flag = rng.gen_bool(team_win_percentage);
+    record.is_valid = true;
+}
+
+// ノックアウト方式のステージを組み合わせの順に1回分シミュレーションする
+// seeds は SflStage::get_knockout_seeds の並びで、get_seed_count 以上あること
+// 行われた試合と勝者を返す。最後の節の勝者が次のステージに進む
+pub fn simulate_knockout(
+    sfl_stage: SflStage,
+    seeds: &[SflTeam],
+    rate_key_function: RateKeyFunction,
+    rating_map: &HashMap<RatingKey, f64>,
+    player_rating: Option<&SflPlayerRating>,
+    rng: &mut StdRng,
+) -> Vec<(SflMatch, SflTeam)> {
+    let mut results: Vec<(SflMatch, SflTeam)> = vec![];
+    for bracket_match in sfl_stage.get_bracket().into_iter() {
+        let sfl_match = SflMatch {
+            section: bracket_match.section,
+            branch: bracket_match.branch,
+            sfl_stage,
+            team: resolve_slot(&bracket_match.slot, seeds, &results),
+            opponent_team: resolve_slot(&bracket_match.opponent_slot, seeds, &results),
+            // 上位シードがホーム
+            is_home: true,
+        };
+        let mut records = sfl_stage.match_to_records(&sfl_match);
+        for record in records.iter_mut() {
+            predict_record(record, rate_key_function, rating_map, player_rating, rng);
+        }
+        sfl_stage.correct_records(&mut records);
+        let winner = if sfl_stage.get_knockout_winner(&records).unwrap() {
+            sfl_match.team.to_owned()
+        } else {
+            sfl_match.opponent_team.to_owned()
+        };
+        results.push((sfl_match, winner));
+    }
+    results
+}
+
+fn resolve_slot(slot: &SflSlot, seeds: &[SflTeam], results: &[(SflMatch, SflTeam)]) -> SflTeam {
+    match slot {
+        SflSlot::Seed(n) => seeds
+            .get(*n)
+            .unwrap_or_else(|| panic!("シード {} がない（{} チーム）", n, seeds.len()))
+            .to_owned(),
+        SflSlot::Winner(section, branch) => results
+            .iter()
+            .find(|(m, _)| m.section == *section && m.branch == *branch)
+            .map(|(_, winner)| winner.to_owned())
+            .unwrap_or_else(|| panic!("{}節 第{}試合がまだ行われていない", section, branch)),
+    }
+}
@@ -0,0 +1,428 @@
+use crate::elo::{
+    Character, EloRecord, EloSetting, Player, Tournament, TournamentSub, TournamentTier,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::btree_map::Entry;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
+use std::path::Path;
+
+// start.gg のブラケットを書き出した JSON から EloSetting と EloRecord を作る
+// イベントを Tournament、フェーズを TournamentSub、セットを EloRecord にする
+// start.gg の id と Player などの u32 の id の対応は StartggIdMap に保存して、何度取り込んでも同じ id にする
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StartggFile {
+    pub events: Vec<StartggEvent>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StartggEvent {
+    pub id: u64,
+    pub name: String,
+    // UNIX 時間（秒）。セットに completedAt がなければこの日付にする
+    pub start_at: u64,
+    pub phases: Vec<StartggPhase>,
+    pub entrants: Vec<StartggEntrant>,
+    pub sets: Vec<StartggSet>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StartggPhase {
+    pub id: u64,
+    pub name: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StartggEntrant {
+    pub id: u64,
+    pub name: String,
+    // イベントをまたいで同じ選手を表す id
+    pub player_id: u64,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StartggSet {
+    pub id: u64,
+    pub phase_id: u64,
+    #[serde(default)]
+    pub completed_at: Option<u64>,
+    pub entrant1_id: u64,
+    pub entrant2_id: u64,
+    // 失格は負の値。なければ games から数える
+    #[serde(default)]
+    pub entrant1_score: Option<i32>,
+    #[serde(default)]
+    pub entrant2_score: Option<i32>,
+    #[serde(default)]
+    pub games: Vec<StartggGame>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StartggGame {
+    pub winner_id: u64,
+    #[serde(default)]
+    pub selections: Vec<StartggSelection>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StartggSelection {
+    pub entrant_id: u64,
+    pub character: StartggCharacter,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StartggCharacter {
+    pub id: u64,
+    pub name: String,
+}
+
+// キャラクターの選択がないセットに使うキャラクター（start.gg のキャラクター id は 1 以上）
+const UNKNOWN_CHARACTER_ID: u64 = 0;
+const UNKNOWN_CHARACTER_NAME: &str = "不明";
+
+// start.gg の id → u32 の id。新しい id には種類ごとに今までの最大 + 1 を割り当てる
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct StartggIdMap {
+    pub players: BTreeMap<u64, u32>,
+    pub tournaments: BTreeMap<u64, u32>,
+    pub tournament_subs: BTreeMap<u64, u32>,
+    pub characters: BTreeMap<u64, u32>,
+}
+
+// 対応表の1種類に id を割り当てる。次の id は最初に一度だけ求めておく
+struct IdAssigner<'a> {
+    map: &'a mut BTreeMap<u64, u32>,
+    next_id: u32,
+}
+
+impl<'a> IdAssigner<'a> {
+    fn new(map: &'a mut BTreeMap<u64, u32>) -> IdAssigner<'a> {
+        let next_id = map.values().max().map_or(1, |id| id + 1);
+        IdAssigner { map, next_id }
+    }
+    fn get_or_insert(&mut self, external_id: u64) -> u32 {
+        match self.map.entry(external_id) {
+            Entry::Occupied(entry) => *entry.get(),
+            Entry::Vacant(entry) => {
+                let id = self.next_id;
+                self.next_id += 1;
+                *entry.insert(id)
+            }
+        }
+    }
+}
+
+// ファイルがなければ空の対応表から始める
+pub fn load_id_map(path: &str) -> Result<StartggIdMap, String> {
+    if !Path::new(path).exists() {
+        return Ok(StartggIdMap::default());
+    }
+    let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+    serde_json::from_str(&text).map_err(|e| format!("{}: {}", path, e))
+}
+
+pub fn save_id_map(path: &str, id_map: &StartggIdMap) -> Result<(), String> {
+    let text = serde_json::to_string_pretty(id_map).map_err(|e| e.to_string())?;
+    fs::write(path, text).map_err(|e| format!("{}: {}", path, e))
+}
+
+pub fn load_startgg(
+    path: &str,
+    id_map: &mut StartggIdMap,
+    tier: TournamentTier,
+) -> Result<(EloSetting, Vec<EloRecord>), String> {
+    let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+    parse_startgg(&text, id_map, tier).map_err(|e| format!("{}: {}", path, e))
+}
+
+// tier は取り込むイベントすべての格（start.gg には格がないので指定する）
+// 失格のセットと、勝敗のわからないセットは取り込まない
+// エラーのときは id_map を変えない
+pub fn parse_startgg(
+    text: &str,
+    id_map: &mut StartggIdMap,
+    tier: TournamentTier,
+) -> Result<(EloSetting, Vec<EloRecord>), String> {
+    let startgg_file: StartggFile = serde_json::from_str(text).map_err(|e| e.to_string())?;
+    // 写しに割り当てて、取り込めたときだけ戻す
+    let mut new_id_map = id_map.clone();
+    let StartggIdMap {
+        players,
+        tournaments,
+        tournament_subs,
+        characters,
+    } = &mut new_id_map;
+    let mut player_ids = IdAssigner::new(players);
+    let mut tournament_ids = IdAssigner::new(tournaments);
+    let mut tournament_sub_ids = IdAssigner::new(tournament_subs);
+    let mut character_ids = IdAssigner::new(characters);
+    let mut player_list: Vec<Player> = vec![];
+    // id → player_list での位置
+    let mut player_index: HashMap<u32, usize> = HashMap::new();
+    let mut tournament_list: Vec<Tournament> = vec![];
+    let mut tournament_sub_list: Vec<TournamentSub> = vec![];
+    let mut character_list: Vec<Character> = vec![];
+    let mut character_id_set: HashSet<u32> = HashSet::new();
+    let mut record_list: Vec<EloRecord> = vec![];
+
+    let mut add_character = |character: &StartggCharacter| {
+        let id = character_ids.get_or_insert(character.id);
+        if character_id_set.insert(id) {
+            character_list.push(Character {
+                id,
+                name: character.name.to_owned(),
+            });
+        }
+        id
+    };
+    let unknown_character = StartggCharacter {
+        id: UNKNOWN_CHARACTER_ID,
+        name: UNKNOWN_CHARACTER_NAME.to_string(),
+    };
+
+    for event in startgg_file.events.iter() {
+        let tournament_id = tournament_ids.get_or_insert(event.id);
+        tournament_list.push(Tournament {
+            id: tournament_id,
+            name: event.name.to_owned(),
+            tier,
+        });
+        // start.gg のフェーズ id → TournamentSub の id
+        let mut phase_ids: HashMap<u64, u32> = HashMap::new();
+        for phase in event.phases.iter() {
+            let id = tournament_sub_ids.get_or_insert(phase.id);
+            phase_ids.insert(phase.id, id);
+            tournament_sub_list.push(TournamentSub {
+                id,
+                name: phase.name.to_owned(),
+            });
+        }
+        // エントラント id → Player の id
+        let mut entrant_player_ids: HashMap<u64, u32> = HashMap::new();
+        for entrant in event.entrants.iter() {
+            // イベントによってタグが違えば別名にする
+            let id = player_ids.get_or_insert(entrant.player_id);
+            entrant_player_ids.entry(entrant.id).or_insert(id);
+            match player_index.get(&id) {
+                Some(index) => player_list[*index].add_alias(&entrant.name),
+                None => {
+                    player_index.insert(id, player_list.len());
+                    player_list.push(Player::new(id, &entrant.name));
+                }
+            }
+        }
+
+        for set in event.sets.iter() {
+            let find_player_id = |entrant_id: u64| {
+                entrant_player_ids.get(&entrant_id).copied().ok_or(format!(
+                    "{} セット{}: 不明なエントラント {}",
+                    event.name, set.id, entrant_id
+                ))
+            };
+            let player_id = find_player_id(set.entrant1_id)?;
+            let opponent_player_id = find_player_id(set.entrant2_id)?;
+            let tournament_sub_id = *phase_ids.get(&set.phase_id).ok_or(format!(
+                "{} セット{}: 不明なフェーズ {}",
+                event.name, set.id, set.phase_id
+            ))?;
+            for game in set.games.iter() {
+                if game.winner_id != set.entrant1_id && game.winner_id != set.entrant2_id {
+                    return Err(format!(
+                        "{} セット{}: ゲームの勝者 {} がセットのエントラントではない",
+                        event.name, set.id, game.winner_id
+                    ));
+                }
+            }
+            let game_results: Vec<bool> = set
+                .games
+                .iter()
+                .map(|game| game.winner_id == set.entrant1_id)
+                .collect();
+            let (win_count, lose_count) = match (set.entrant1_score, set.entrant2_score) {
+                (Some(score1), Some(score2)) => {
+                    // 失格
+                    if score1 < 0 || score2 < 0 {
+                        continue;
+                    }
+                    (score1 as u32, score2 as u32)
+                }
+                _ => {
+                    let win_count = game_results.iter().filter(|w| **w).count() as u32;
+                    (win_count, game_results.len() as u32 - win_count)
+                }
+            };
+            if win_count + lose_count == 0 {
+                continue;
+            }
+
+            // ゲームごとの選択で最も多く使ったキャラクター（同数なら先に使った方）
+            let mut get_character_id = |entrant_id: u64| {
+                let mut counts: Vec<(&StartggCharacter, u32)> = vec![];
+                for selection in set
+                    .games
+                    .iter()
+                    .flat_map(|game| game.selections.iter())
+                    .filter(|s| s.entrant_id == entrant_id)
+                {
+                    match counts
+                        .iter_mut()
+                        .find(|(c, _)| c.id == selection.character.id)
+                    {
+                        Some((_, count)) => *count += 1,
+                        None => counts.push((&selection.character, 1)),
+                    }
+                }
+                let mut main_character: Option<(&StartggCharacter, u32)> = None;
+                for (character, count) in counts.into_iter() {
+                    if main_character.is_none_or(|(_, main_count)| count > main_count) {
+                        main_character = Some((character, count));
+                    }
+                }
+                let character = main_character.map_or(&unknown_character, |(c, _)| c);
+                add_character(character)
+            };
+            let character_id = get_character_id(set.entrant1_id);
+            let opponent_character_id = get_character_id(set.entrant2_id);
+
+            let mut record = EloRecord::new(
+                tournament_id,
+                tournament_sub_id,
+                set.completed_at.unwrap_or(event.start_at),
+                player_id,
+                character_id,
+                opponent_player_id,
+                opponent_character_id,
+                win_count,
+                lose_count,
+            );
+            // スコアとゲームの数が合うときだけ順番を使う
+            let game_win_count = game_results.iter().filter(|w| **w).count() as u32;
+            if game_win_count == win_count
+                && game_results.len() as u32 - game_win_count == lose_count
+            {
+                record = record.with_game_results(game_results);
+            }
+            record_list.push(record);
+        }
+    }
+    *id_map = new_id_map;
+    Ok((
+        EloSetting::new(
+            player_list,
+            tournament_list,
+            tournament_sub_list,
+            character_list,
+        ),
+        record_list,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 選手 A (7000) と B (7001) の1イベント。sets は JSON の配列の中身
+    fn create_file(event_id: u64, entrants: &str, sets: &str) -> String {
+        format!(
+            r#"{{ "events": [{{ "id": {}, "name": "e", "startAt": 1714000000,
+                "phases": [{{ "id": 55, "name": "Pools" }}],
+                "entrants": [{}],
+                "sets": [{}] }}] }}"#,
+            event_id, entrants, sets
+        )
+    }
+
+    const ENTRANTS: &str =
+        r#"{ "id": 1, "name": "A", "playerId": 7000 }, { "id": 2, "name": "B", "playerId": 7001 }"#;
+
+    fn create_set(id: u64, score1: i32, score2: i32) -> String {
+        format!(
+            r#"{{ "id": {}, "phaseId": 55, "entrant1Id": 1, "entrant2Id": 2, "entrant1Score": {}, "entrant2Score": {} }}"#,
+            id, score1, score2
+        )
+    }
+
+    #[test]
+    fn same_file_gives_same_ids() {
+        let text = create_file(900, ENTRANTS, &create_set(10, 2, 1));
+        let mut id_map = StartggIdMap::default();
+        let (_, first) = parse_startgg(&text, &mut id_map, TournamentTier::Weekly).unwrap();
+        let players = id_map.players.clone();
+        let (_, second) = parse_startgg(&text, &mut id_map, TournamentTier::Weekly).unwrap();
+        assert_eq!(id_map.players, players);
+        assert_eq!(
+            (first[0].player_id, first[0].opponent_player_id),
+            (second[0].player_id, second[0].opponent_player_id)
+        );
+        assert_eq!(id_map.tournaments, BTreeMap::from([(900, 1)]));
+        assert_eq!(id_map.tournament_subs, BTreeMap::from([(55, 1)]));
+    }
+
+    #[test]
+    fn new_player_gets_next_id() {
+        let mut id_map = StartggIdMap::default();
+        id_map.players.insert(7000, 3);
+        id_map.players.insert(5000, 8);
+        let text = create_file(900, ENTRANTS, &create_set(10, 2, 0));
+        let (elo_setting, record_list) =
+            parse_startgg(&text, &mut id_map, TournamentTier::Weekly).unwrap();
+        assert_eq!(id_map.players[&7000], 3);
+        assert_eq!(id_map.players[&7001], 9);
+        assert_eq!(elo_setting.get_player_ids(), vec![3, 9]);
+        assert_eq!(
+            (record_list[0].player_id, record_list[0].opponent_player_id),
+            (3, 9)
+        );
+    }
+
+    #[test]
+    fn skips_disqualified_and_empty_sets() {
+        let sets = [
+            create_set(10, 2, 1),
+            create_set(11, -1, 0),
+            create_set(12, 0, 0),
+            r#"{ "id": 13, "phaseId": 55, "entrant1Id": 1, "entrant2Id": 2 }"#.to_string(),
+        ]
+        .join(",");
+        let mut id_map = StartggIdMap::default();
+        let (_, record_list) = parse_startgg(
+            &create_file(900, ENTRANTS, &sets),
+            &mut id_map,
+            TournamentTier::Weekly,
+        )
+        .unwrap();
+        assert_eq!(record_list.len(), 1);
+        assert_eq!(
+            (record_list[0].win_count, record_list[0].lose_count),
+            (2, 1)
+        );
+    }
+
+    #[test]
+    fn error_leaves_id_map_untouched() {
+        let mut id_map = StartggIdMap::default();
+        id_map.players.insert(7000, 1);
+        let set = r#"{ "id": 10, "phaseId": 55, "entrant1Id": 1, "entrant2Id": 3, "entrant1Score": 2, "entrant2Score": 0 }"#;
+        let error = match parse_startgg(
+            &create_file(900, ENTRANTS, set),
+            &mut id_map,
+            TournamentTier::Weekly,
+        ) {
+            Ok(_) => panic!("エラーにならない"),
+            Err(e) => e,
+        };
+        assert_eq!(error, "e セット10: 不明なエントラント 3");
+        assert_eq!(id_map.players, BTreeMap::from([(7000, 1)]));
+        assert!(id_map.tournaments.is_empty());
+        assert!(id_map.tournament_subs.is_empty());
+    }
+}
@@ -0,0 +1,325 @@
+use crate::elo::{EloRecord, EloSetting};
+use crate::report::format_percentage;
+use std::cmp::Ordering;
+
+// EloRecord の集計（対戦成績、キャラ相性、大会ごとの成績）
+// 1つの EloRecord を1セット、win_count, lose_count を本数として数える
+
+// player 側から見た成績
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RecordStat {
+    pub set_wins: u32,
+    pub set_losses: u32,
+    // 勝ち数と負け数が同じセット
+    pub set_draws: u32,
+    pub game_wins: u32,
+    pub game_losses: u32,
+}
+
+impl RecordStat {
+    fn add(&mut self, win_count: u32, lose_count: u32) {
+        match win_count.cmp(&lose_count) {
+            Ordering::Greater => self.set_wins += 1,
+            Ordering::Less => self.set_losses += 1,
+            Ordering::Equal => self.set_draws += 1,
+        }
+        self.game_wins += win_count;
+        self.game_losses += lose_count;
+    }
+    // 本数での勝率。1本もなければ None
+    pub fn get_game_win_percentage(&self) -> Option<f64> {
+        let game_count = self.game_wins + self.game_losses;
+        if game_count == 0 {
+            None
+        } else {
+            Some(self.game_wins as f64 / game_count as f64)
+        }
+    }
+}
+
+// EloRecord を (自分, 自分のキャラクター, 相手, 相手のキャラクター, 勝ち数, 負け数) として、両方のプレイヤーから見た向きで並べる
+fn get_sides(r: &EloRecord) -> [(u32, u32, u32, u32, u32, u32); 2] {
+    [
+        (
+            r.player_id,
+            r.character_id,
+            r.opponent_player_id,
+            r.opponent_character_id,
+            r.win_count,
+            r.lose_count,
+        ),
+        (
+            r.opponent_player_id,
+            r.opponent_character_id,
+            r.player_id,
+            r.character_id,
+            r.lose_count,
+            r.win_count,
+        ),
+    ]
+}
+
+// player_id から見た opponent_player_id との対戦成績
+pub fn get_head_to_head(
+    record_list: &[EloRecord],
+    player_id: u32,
+    opponent_player_id: u32,
+) -> RecordStat {
+    let mut stat = RecordStat::default();
+    for r in record_list.iter() {
+        for (player, _, opponent_player, _, win_count, lose_count) in get_sides(r) {
+            if player == player_id && opponent_player == opponent_player_id {
+                stat.add(win_count, lose_count);
+            }
+        }
+    }
+    stat
+}
+
+// キャラクター同士の相性表。(キャラクター, 相手のキャラクター, 成績) をキャラクターの id 順に並べる
+// 両方のキャラクターから見た向きで数えるので、A 対 B と B 対 A の両方の行がある
+// 同キャラ戦は勝ちと負けが同じ数になる
+pub fn get_matchup_table(record_list: &[EloRecord]) -> Vec<(u32, u32, RecordStat)> {
+    let mut table: Vec<(u32, u32, RecordStat)> = vec![];
+    for r in record_list.iter() {
+        for (_, character, _, opponent_character, win_count, lose_count) in get_sides(r) {
+            let index = match table
+                .iter()
+                .position(|(c, o, _)| *c == character && *o == opponent_character)
+            {
+                Some(index) => index,
+                None => {
+                    table.push((character, opponent_character, RecordStat::default()));
+                    table.len() - 1
+                }
+            };
+            table[index].2.add(win_count, lose_count);
+        }
+    }
+    table.sort_by_key(|(character, opponent_character, _)| (*character, *opponent_character));
+    table
+}
+
+// 大会に出たプレイヤーごとの成績。セットの勝ち数の多い順（同じなら本数の得失、player_id の順）
+pub fn get_tournament_results(
+    record_list: &[EloRecord],
+    tournament_id: u32,
+) -> Vec<(u32, RecordStat)> {
+    let mut results: Vec<(u32, RecordStat)> = vec![];
+    for r in record_list
+        .iter()
+        .filter(|r| r.tournament_id == tournament_id)
+    {
+        for (player, _, _, _, win_count, lose_count) in get_sides(r) {
+            let index = match results.iter().position(|(p, _)| *p == player) {
+                Some(index) => index,
+                None => {
+                    results.push((player, RecordStat::default()));
+                    results.len() - 1
+                }
+            };
+            results[index].1.add(win_count, lose_count);
+        }
+    }
+    results.sort_by(|(a_player, a), (b_player, b)| {
+        b.set_wins
+            .cmp(&a.set_wins)
+            .then(
+                (b.game_wins as i64 - b.game_losses as i64)
+                    .cmp(&(a.game_wins as i64 - a.game_losses as i64)),
+            )
+            .then(a_player.cmp(b_player))
+    });
+    results
+}
+
+// 名前がわからなければ id をそのまま使う
+pub fn get_player_name(elo_setting: &EloSetting, player_id: u32) -> String {
+    elo_setting
+        .find_player(player_id)
+        .map_or(player_id.to_string(), |p| p.name.to_owned())
+}
+
+pub fn get_character_name(elo_setting: &EloSetting, character_id: u32) -> String {
+    elo_setting
+        .find_character(character_id)
+        .map_or(character_id.to_string(), |c| c.name.to_owned())
+}
+
+pub fn get_tournament_name(elo_setting: &EloSetting, tournament_id: u32) -> String {
+    elo_setting
+        .find_tournament(tournament_id)
+        .map_or(tournament_id.to_string(), |t| t.name.to_owned())
+}
+
+// 以下は report.rs と同じくタブ区切りの表にする
+
+fn format_stat(stat: &RecordStat) -> String {
+    format!(
+        "{}\t{}\t{}\t{}\t{}\t{}",
+        stat.set_wins,
+        stat.set_losses,
+        stat.set_draws,
+        stat.game_wins,
+        stat.game_losses,
+        stat.get_game_win_percentage()
+            .map_or("-".to_string(), format_percentage)
+    )
+}
+
+const STAT_HEADER: &str = "勝\t負\t分\t本数勝\t本数負\t本数勝率";
+
+pub fn format_head_to_head(
+    elo_setting: &EloSetting,
+    player_id: u32,
+    opponent_player_id: u32,
+    stat: &RecordStat,
+) -> String {
+    [
+        format!("PLAYER\tOPPONENT\t{}", STAT_HEADER),
+        format!(
+            "{}\t{}\t{}",
+            get_player_name(elo_setting, player_id),
+            get_player_name(elo_setting, opponent_player_id),
+            format_stat(stat)
+        ),
+    ]
+    .join("\n")
+}
+
+pub fn format_matchup_table(elo_setting: &EloSetting, table: &[(u32, u32, RecordStat)]) -> String {
+    let mut lines: Vec<String> = vec![format!("CHARACTER\tOPPONENT\t{}", STAT_HEADER)];
+    for (character_id, opponent_character_id, stat) in table.iter() {
+        lines.push(format!(
+            "{}\t{}\t{}",
+            get_character_name(elo_setting, *character_id),
+            get_character_name(elo_setting, *opponent_character_id),
+            format_stat(stat)
+        ));
+    }
+    lines.join("\n")
+}
+
+pub fn format_tournament_results(
+    elo_setting: &EloSetting,
+    tournament_id: u32,
+    results: &[(u32, RecordStat)],
+) -> String {
+    let mut lines: Vec<String> = vec![
+        get_tournament_name(elo_setting, tournament_id),
+        format!("PLAYER\t{}", STAT_HEADER),
+    ];
+    for (player_id, stat) in results.iter() {
+        lines.push(format!(
+            "{}\t{}",
+            get_player_name(elo_setting, *player_id),
+            format_stat(stat)
+        ));
+    }
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::elo::{Character, Player, Tournament, TournamentSub, TournamentTier};
+
+    fn stat(
+        set_wins: u32,
+        set_losses: u32,
+        set_draws: u32,
+        game_wins: u32,
+        game_losses: u32,
+    ) -> RecordStat {
+        RecordStat {
+            set_wins,
+            set_losses,
+            set_draws,
+            game_wins,
+            game_losses,
+        }
+    }
+
+    // (大会, プレイヤー, キャラクター, 相手, 相手のキャラクター, 勝ち数, 負け数)
+    fn create_records(records: &[(u32, u32, u32, u32, u32, u32, u32)]) -> Vec<EloRecord> {
+        records
+            .iter()
+            .map(|(tournament_id, p, c, o, oc, w, l)| {
+                EloRecord::new(*tournament_id, 1, 1, *p, *c, *o, *oc, *w, *l)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn head_to_head_counts_both_directions() {
+        let record_list = create_records(&[
+            (1, 1, 1, 2, 2, 2, 1),
+            (1, 2, 2, 1, 1, 2, 0),
+            (2, 1, 1, 2, 2, 1, 1),
+            (2, 1, 1, 3, 2, 2, 0),
+        ]);
+        assert_eq!(get_head_to_head(&record_list, 1, 2), stat(1, 1, 1, 3, 4));
+        assert_eq!(get_head_to_head(&record_list, 2, 1), stat(1, 1, 1, 4, 3));
+        assert_eq!(get_head_to_head(&record_list, 2, 3), RecordStat::default());
+        assert_eq!(RecordStat::default().get_game_win_percentage(), None);
+        assert_eq!(stat(1, 0, 0, 3, 1).get_game_win_percentage(), Some(0.75));
+    }
+
+    #[test]
+    fn matchup_table_has_both_sides_and_mirrors() {
+        let record_list = create_records(&[(1, 1, 2, 2, 1, 2, 1), (1, 1, 1, 2, 1, 2, 0)]);
+        assert_eq!(
+            get_matchup_table(&record_list),
+            vec![
+                (1, 1, stat(1, 1, 0, 2, 2)),
+                (1, 2, stat(0, 1, 0, 1, 2)),
+                (2, 1, stat(1, 0, 0, 2, 1)),
+            ]
+        );
+    }
+
+    #[test]
+    fn tournament_results_are_ordered_by_sets_then_games() {
+        let record_list = create_records(&[
+            (1, 1, 1, 2, 1, 2, 0),
+            (1, 3, 1, 4, 1, 2, 1),
+            (1, 2, 1, 3, 1, 2, 0),
+            (2, 4, 1, 1, 1, 2, 0),
+        ]);
+        let results = get_tournament_results(&record_list, 1);
+        let order: Vec<u32> = results.iter().map(|(player_id, _)| *player_id).collect();
+        // 1 勝の 1, 2, 3 は本数の得失（+2, 0, -1）の順
+        assert_eq!(order, vec![1, 2, 3, 4]);
+        assert_eq!(results[3].1, stat(0, 1, 0, 1, 2));
+    }
+
+    #[test]
+    fn names_fall_back_to_ids() {
+        let elo_setting = EloSetting::new(
+            vec![Player::new(1, "a")],
+            vec![Tournament {
+                id: 1,
+                name: "t".to_string(),
+                tier: TournamentTier::Weekly,
+            }],
+            vec![TournamentSub {
+                id: 1,
+                name: "s".to_string(),
+            }],
+            vec![Character {
+                id: 1,
+                name: "c".to_string(),
+            }],
+        );
+        assert_eq!(get_player_name(&elo_setting, 1), "a");
+        assert_eq!(get_player_name(&elo_setting, 2), "2");
+        assert_eq!(get_character_name(&elo_setting, 1), "c");
+        assert_eq!(get_tournament_name(&elo_setting, 3), "3");
+        assert_eq!(
+            format_head_to_head(&elo_setting, 1, 2, &stat(1, 0, 0, 2, 0))
+                .lines()
+                .nth(1),
+            Some(format!("a\t2\t1\t0\t0\t2\t0\t{}", format_percentage(1_f64)).as_str())
+        );
+    }
+}
@@ -0,0 +1,189 @@
+use crate::sfl::{SflDivision, SflRecord, SflTeam};
+use std::collections::HashMap;
+use std::f64::consts::{PI, SQRT_2};
+
+// TrueSkill 形式の選手ごとのレートで SFL のセットを予想する
+// セットに出た選手がわかればその選手同士、わからなければチームのメンバー全員の平均をチームの強さとする
+// メンバーを入れ替えると予想が変わるので、ロースター変更や選手交代を反映できる
+
+#[derive(Clone, Debug)]
+pub struct TrueSkillSetting {
+    // 初期レート（Elo と同じ 1500 基準のスケール）
+    pub initial_mu: f64,
+    pub initial_sigma: f64,
+    // 1セットの出来のばらつき
+    pub beta: f64,
+    // セットごとに sigma に足す揺らぎ
+    pub tau: f64,
+}
+
+impl Default for TrueSkillSetting {
+    // TrueSkill の標準値 (25, 25/3, 25/6, 25/300) を 60 倍したもの
+    fn default() -> Self {
+        TrueSkillSetting {
+            initial_mu: 1500_f64,
+            initial_sigma: 500_f64,
+            beta: 250_f64,
+            tau: 5_f64,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct SkillRating {
+    pub mu: f64,
+    pub sigma: f64,
+}
+
+#[derive(Clone, Debug)]
+pub struct SflPlayerRating {
+    pub setting: TrueSkillSetting,
+    // チームのメンバー（SflDivision::get_team_members）。メンバーのわからないチームはチーム名を1人の選手として扱う
+    pub team_members: HashMap<SflTeam, Vec<String>>,
+    pub skill_map: HashMap<String, SkillRating>,
+}
+
+impl SflPlayerRating {
+    pub fn new(setting: TrueSkillSetting, division: &SflDivision) -> SflPlayerRating {
+        let mut player_rating = SflPlayerRating {
+            setting,
+            team_members: HashMap::new(),
+            skill_map: HashMap::new(),
+        };
+        for team in division.teams.iter() {
+            let mut members = division.get_team_members(team);
+            if members.is_empty() {
+                members.push(team.0.to_owned());
+            }
+            player_rating.team_members.insert(team.to_owned(), members);
+        }
+        player_rating
+    }
+    // 別のディビジョンの選手とメンバーを加える（ノックアウト方式のステージ用）
+    pub fn extend(&mut self, other: SflPlayerRating) {
+        self.team_members.extend(other.team_members);
+        self.skill_map.extend(other.skill_map);
+    }
+    pub fn get_skill(&self, player: &str) -> SkillRating {
+        self.skill_map.get(player).copied().unwrap_or(SkillRating {
+            mu: self.setting.initial_mu,
+            sigma: self.setting.initial_sigma,
+        })
+    }
+    // メンバーの mu の平均
+    pub fn get_team_rating(&self, team: &SflTeam) -> f64 {
+        let members = self.get_members(team, None);
+        members.iter().map(|p| self.get_skill(p).mu).sum::<f64>() / members.len() as f64
+    }
+    // team 側がセットを取る確率
+    pub fn get_win_percentage(&self, record: &SflRecord) -> f64 {
+        let (members, opponent_members) = self.get_record_members(record);
+        let (mu, variance) = self.get_performance(&members);
+        let (opponent_mu, opponent_variance) = self.get_performance(&opponent_members);
+        normal_cdf((mu - opponent_mu) / (variance + opponent_variance).sqrt())
+    }
+    // セットの結果で両チームの出場選手（わからなければメンバー全員）を更新する
+    pub fn update(&mut self, record: &SflRecord) {
+        let (members, opponent_members) = self.get_record_members(record);
+        if members.iter().any(|p| opponent_members.contains(p)) {
+            return;
+        }
+        // 前のセットからの揺らぎを加える
+        let tau = self.setting.tau;
+        for player in members.iter().chain(opponent_members.iter()) {
+            let mut skill = self.get_skill(player);
+            skill.sigma = (skill.sigma * skill.sigma + tau * tau).sqrt();
+            self.skill_map.insert(player.to_owned(), skill);
+        }
+        let (winners, losers) = if record.win_flag {
+            (members, opponent_members)
+        } else {
+            (opponent_members, members)
+        };
+        let (winner_mu, winner_variance) = self.get_performance(&winners);
+        let (loser_mu, loser_variance) = self.get_performance(&losers);
+        let c = (winner_variance + loser_variance).sqrt();
+        let t = (winner_mu - loser_mu) / c;
+        let v = normal_pdf(t) / normal_cdf(t).max(f64::MIN_POSITIVE);
+        let w = v * (v + t);
+        for (players, sign) in [(winners, 1_f64), (losers, -1_f64)] {
+            let weight = 1_f64 / players.len() as f64;
+            for player in players.iter() {
+                let skill = self.get_skill(player);
+                let variance = skill.sigma * skill.sigma;
+                let mu = skill.mu + sign * weight * variance / c * v;
+                let variance_factor = 1_f64 - weight * weight * variance / (c * c) * w;
+                self.skill_map.insert(
+                    player.to_owned(),
+                    SkillRating {
+                        mu,
+                        sigma: (variance * variance_factor.max(0.0001)).sqrt(),
+                    },
+                );
+            }
+        }
+    }
+    fn get_members(&self, team: &SflTeam, player: Option<&String>) -> Vec<String> {
+        match player {
+            Some(player) => vec![player.to_owned()],
+            None => self
+                .team_members
+                .get(team)
+                .cloned()
+                .unwrap_or(vec![team.0.to_owned()]),
+        }
+    }
+    fn get_record_members(&self, record: &SflRecord) -> (Vec<String>, Vec<String>) {
+        let players = record.players.as_ref();
+        (
+            self.get_members(&record.sfl_match.team, players.map(|p| &p.player)),
+            self.get_members(
+                &record.sfl_match.opponent_team,
+                players.map(|p| &p.opponent_player),
+            ),
+        )
+    }
+    // 1人が出るセットのチームとしての出来を、メンバーの重み 1/n の和として (平均, 分散) で返す
+    fn get_performance(&self, players: &[String]) -> (f64, f64) {
+        let weight = 1_f64 / players.len() as f64;
+        let beta = self.setting.beta;
+        players
+            .iter()
+            .fold((0_f64, 0_f64), |(mu, variance), player| {
+                let skill = self.get_skill(player);
+                (
+                    mu + weight * skill.mu,
+                    variance + weight * weight * (skill.sigma * skill.sigma + beta * beta),
+                )
+            })
+    }
+}
+
+fn normal_pdf(x: f64) -> f64 {
+    (-x * x / 2_f64).exp() / (2_f64 * PI).sqrt()
+}
+
+fn normal_cdf(x: f64) -> f64 {
+    0.5 * erfc(-x / SQRT_2)
+}
+
+// 相補誤差関数の近似（Numerical Recipes の erfcc、相対誤差 1.2e-7 以下）
+fn erfc(x: f64) -> f64 {
+    let z = x.abs();
+    let t = 1_f64 / (1_f64 + 0.5 * z);
+    let r = t
+        * (-z * z - 1.26551223
+            + t * (1.00002368
+                + t * (0.37409196
+                    + t * (0.09678418
+                        + t * (-0.18628806
+                            + t * (0.27886807
+                                + t * (-1.13520398
+                                    + t * (1.48851587 + t * (-0.82215223 + t * 0.17087277)))))))))
+            .exp();
+    if x >= 0_f64 {
+        r
+    } else {
+        2_f64 - r
+    }
+}